@@ -1,7 +1,10 @@
 use std::{sync::Arc, time::Duration};
 
 use actix_web::{App, HttpResponse, HttpServer, Responder, web};
-use busybody::ServiceContainer;
+use busybody::{
+    ServiceContainer,
+    actix::{Inject, RequestScope},
+};
 use chrono::prelude::*;
 use rand::{self, Rng};
 
@@ -16,6 +19,7 @@ async fn main() -> std::io::Result<()> {
 
     HttpServer::new(|| {
         App::new()
+            .wrap(RequestScope::new())
             .route("/", web::get().to(uptime))
             .route("/two", web::get().to(uptime2))
     })
@@ -103,11 +107,8 @@ async fn uptime() -> impl Responder {
         .content_type("text/html")
         .body(format!("<h1><center>{}</center></h1>", timer.duration()))
 }
-async fn uptime2() -> impl Responder {
-    // 5. Ask for an instance of HandlerExecutionTime to be created and provided
-    let timer = busybody::helpers::get_type::<Arc<HandlerExecutionTime>>()
-        .await
-        .unwrap();
+async fn uptime2(Inject(timer): Inject<Arc<HandlerExecutionTime>>) -> impl Responder {
+    // 6. Or let the `Inject` extractor create and provide the instance
     let mut rang = rand::rng();
 
     for _ in 0..rang.random_range(1..40000000) {
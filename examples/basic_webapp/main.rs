@@ -1,5 +1,8 @@
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use busybody::{helpers::service_container, ServiceContainerBuilder};
+use busybody::{
+    actix::{InjectService, RequestScope},
+    ServiceContainerBuilder,
+};
 use chrono::prelude::*;
 
 #[actix_web::main]
@@ -16,6 +19,8 @@ async fn main() -> std::io::Result<()> {
     // 3. Setup actix web application
     HttpServer::new(move || {
         App::new()
+            // 4. Create a container for each request. It falls back to the global container
+            .wrap(RequestScope::new())
             .route("/", web::get().to(uptime))
             .route("/two", web::get().to(uptime2))
     })
@@ -24,16 +29,15 @@ async fn main() -> std::io::Result<()> {
     .await
 }
 
-async fn uptime() -> impl Responder {
-    // 4. Get the service container via the function `service_container`
-    let time_keeper = service_container().get::<ServerUptime>().await.unwrap();
+async fn uptime(time_keeper: InjectService<ServerUptime>) -> impl Responder {
+    // 5. The ServerUptime service is injected by the `InjectService` extractor
     HttpResponse::Ok().content_type("text/html").body(format!(
         "<h1><center>Up time<br/>{}</center></h1>",
         time_keeper.duration()
     ))
 }
 async fn uptime2() -> impl Responder {
-    // 5. Or get the ServerUptime service by using the helper function "service"
+    // 6. Or get the ServerUptime service by using the helper function "service"
     let time_keeper = busybody::helpers::service::<ServerUptime>().await;
 
     HttpResponse::Ok().content_type("text/html").body(format!(
@@ -0,0 +1,39 @@
+use busybody::helpers::service_container;
+
+#[tokio::main]
+async fn main() {
+    // 1. The application wide values are stored in the global container
+    service_container().set_type(AppName("busybody")).await;
+
+    // 2. Each tenant gets a child of the global container
+    let tenant = service_container().child();
+    tenant.set_type(Tenant(7)).await;
+
+    // 3. Each request gets a child of the tenant container
+    let request = tenant.child();
+    request.set_type(RequestId(42)).await;
+
+    // 4. Each job gets a child of the request container
+    let job = request.child();
+
+    // 5. The job container walks up the chain: request -> tenant -> global
+    let app = job.get_type::<AppName>().await.unwrap();
+    let tenant_id = job.get_type::<Tenant>().await.unwrap();
+    let request_id = job.get_type::<RequestId>().await.unwrap();
+    println!(
+        "app: {}, tenant: {}, request: {}",
+        app.0, tenant_id.0, request_id.0
+    );
+
+    // 6. Values registered in a child are never visible to its parent
+    assert!(tenant.get_type::<RequestId>().await.is_none());
+}
+
+#[derive(Debug, Clone)]
+struct AppName(&'static str);
+
+#[derive(Debug, Clone)]
+struct Tenant(u32);
+
+#[derive(Debug, Clone)]
+struct RequestId(u32);
@@ -28,6 +28,7 @@ impl busybody::Resolver for HackerNewsCacheClient {
 }
 
 #[async_trait]
+#[allow(clippy::collapsible_if)]
 impl HackerNewsClientTrait for HackerNewsCacheClient {
     async fn fetch_top_stories(&self) -> Option<Vec<u32>> {
         if let Ok(stories) = self.top_stories.read() {
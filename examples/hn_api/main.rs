@@ -10,6 +10,7 @@ use hacker_news_common::{
 };
 
 #[actix_web::main]
+#[allow(clippy::collapsible_if)]
 async fn main() -> std::io::Result<()> {
     busybody::helpers::resolvable_once::<Arc<HackerNewsClientProvider>>().await;
 
@@ -1,5 +1,9 @@
 use axum::{Json, Router, extract::Path, routing::get};
-use busybody::{Service, helpers::service_container};
+use busybody::{
+    Service,
+    axum::{InjectService, RequestScopeLayer},
+    helpers::service_container,
+};
 use hacker_news_common::{
     Config, HackerNewsClientProvider,
     hacker_news_client::{self, HackerNewsClientTrait},
@@ -9,6 +13,7 @@ use hacker_news_common::{
 mod hacker_news_common;
 
 #[tokio::main]
+#[allow(clippy::collapsible_if)]
 async fn main() {
     busybody::helpers::resolvable_once::<Service<HackerNewsClientProvider>>().await;
 
@@ -29,7 +34,8 @@ async fn main() {
     // 2. build our application with a route
     let app = Router::new()
         .route("/", get(top_stories))
-        .route("/item/{id}", get(get_story));
+        .route("/item/{id}", get(get_story))
+        .layer(RequestScopeLayer::new()); // Creates a container for each request
 
     println!("listening on port: 8080");
 
@@ -39,24 +45,18 @@ async fn main() {
     axum::serve(listener, app).await.unwrap();
 }
 
-async fn top_stories() -> Json<Vec<u32>> {
-    // 3. Inject hacker news client provider
-    let list = busybody::helpers::service::<HackerNewsClientProvider>()
-        .await
-        .fetch_top_stories()
-        .await
-        .unwrap();
+// 3. Inject hacker news client provider
+async fn top_stories(client: InjectService<HackerNewsClientProvider>) -> Json<Vec<u32>> {
+    let list = client.fetch_top_stories().await.unwrap();
 
     Json(list)
 }
 
 // basic handler that responds with a static string
-async fn get_story(Path(id): Path<u32>) -> Json<hacker_news_client::Story> {
-    // 3. Inject hacker news client provider
-    let story = busybody::helpers::service::<HackerNewsClientProvider>()
-        .await
-        .fetch_story(id)
-        .await
-        .unwrap();
+async fn get_story(
+    Path(id): Path<u32>,
+    client: InjectService<HackerNewsClientProvider>,
+) -> Json<hacker_news_client::Story> {
+    let story = client.fetch_story(id).await.unwrap();
     Json(story)
 }
@@ -1,4 +1,5 @@
 #[tokio::main]
+#[allow(clippy::bool_assert_comparison)]
 async fn main() {
     // set an i32 in the global container
     busybody::helpers::set_type(600).await;
@@ -6,6 +6,7 @@ use std::fmt::Debug;
 use std::ops::Deref;
 
 #[tokio::main]
+#[allow(clippy::disallowed_names)]
 async fn main() {
     // 1. Setup the container by using the service builder
     let container = ServiceContainerBuilder::new().build();
@@ -0,0 +1,84 @@
+//! Procedural macros for the busybody crate
+//!
+//! Use the re-exports in `busybody` instead of depending on this crate directly
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    Expr, ItemFn, Meta, Token, parse::Parser, parse_macro_input, punctuated::Punctuated,
+    spanned::Spanned,
+};
+
+/// Marks an async function as a test running with its own isolated root container
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as ItemFn);
+    let args = match Punctuated::<Meta, Token![,]>::parse_terminated.parse(args) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    match expand_test(args, input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_test(
+    args: Punctuated<Meta, Token![,]>,
+    input: ItemFn,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if input.sig.asyncness.is_none() {
+        return Err(syn::Error::new(
+            input.sig.fn_token.span(),
+            "the `async` keyword is missing from the function declaration",
+        ));
+    }
+
+    let mut setup = None;
+    let mut runtime_args = Vec::new();
+    for arg in args {
+        match &arg {
+            Meta::NameValue(pair) if pair.path.is_ident("setup") => match &pair.value {
+                Expr::Path(path) => setup = Some(path.clone()),
+                value => {
+                    return Err(syn::Error::new(
+                        value.span(),
+                        "`setup` expects the path of an async function",
+                    ));
+                }
+            },
+            Meta::NameValue(pair) if pair.path.is_ident("crate") => {
+                return Err(syn::Error::new(
+                    pair.span(),
+                    "`crate` is set by busybody and cannot be overridden",
+                ));
+            }
+            _ => runtime_args.push(arg),
+        }
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = input;
+
+    let setup = setup.map(|setup| {
+        quote! { #setup(&__busybody_container).await; }
+    });
+
+    Ok(quote! {
+        #(#attrs)*
+        #[busybody::__private::tokio::test(#(#runtime_args,)* crate = "busybody::__private::tokio")]
+        #vis #sig {
+            let __busybody_container = busybody::ServiceContainerBuilder::isolated().build();
+            busybody::with_container(__busybody_container.clone(), async move {
+                #setup
+                #block
+            })
+            .await
+        }
+    })
+}
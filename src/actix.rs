@@ -0,0 +1,324 @@
+//! Actix web integration
+//!
+//! The `RequestScope` middleware creates a container for every request and seeds it
+//! with the request's method, uri, version and headers. It is a child of the configured
+//! parent, otherwise of the implicit container or a proxy of the global container.
+//! Resolvers of its ancestors are given the request container, so they can use the
+//! request values. Selected `web::Data<T>` values can be bridged into that container
+//! as `Service<T>`.
+//! The `Inject<T>` and `InjectService<T>` extractors resolve their values from that
+//! container and `helpers::*` functions called while handling the request use it as well.
+
+use std::{
+    fmt::Display,
+    future::{Ready, ready},
+    ops::{Deref, DerefMut},
+    rc::Rc,
+    sync::Arc,
+};
+
+use ::actix_web::{
+    Error, FromRequest, HttpMessage, HttpRequest, ResponseError,
+    dev::{Payload, Service as ActixService, ServiceRequest, ServiceResponse, Transform},
+    http::{Method, Uri, Version, header::HeaderMap},
+    web,
+};
+use futures::future::{BoxFuture, LocalBoxFuture};
+
+use crate::{ResolveError, Service, ServiceContainer, current, helpers::service_container};
+
+/// Copies a value from the request's app data into the request's container
+type DataBridge = Arc<dyn Fn(&ServiceRequest, ServiceContainer) -> BoxFuture<'static, ()>>;
+
+/// Extracts an instance of `T` from the request's container
+///
+/// Falls back to the implicit container when the request was not handled
+/// by the `RequestScope` middleware
+#[derive(Debug, Clone)]
+pub struct Inject<T>(pub T);
+
+/// Extracts an instance of `T` wrapped in a `Service<T>` from the request's container
+#[derive(Debug, Clone)]
+pub struct InjectService<T>(pub Service<T>);
+
+/// Returns the container of the request
+pub fn request_container(request: &HttpRequest) -> ServiceContainer {
+    request
+        .extensions()
+        .get::<ServiceContainer>()
+        .cloned()
+        .unwrap_or_else(service_container)
+}
+
+impl<T> FromRequest for Inject<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    type Error = InjectError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let container = request_container(request);
+        Box::pin(async move { Ok(Self(container.try_get_type::<T>().await?)) })
+    }
+}
+
+impl<T> FromRequest for InjectService<T>
+where
+    T: Send + Sync + 'static,
+{
+    type Error = InjectError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let container = request_container(request);
+        Box::pin(async move { Ok(Self(container.try_get_type::<Service<T>>().await?)) })
+    }
+}
+
+impl<T> Deref for Inject<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Inject<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T> Deref for InjectService<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Returned when an extractor could not resolve its type
+#[derive(Debug)]
+pub struct InjectError(pub ResolveError);
+
+impl From<ResolveError> for InjectError {
+    fn from(error: ResolveError) -> Self {
+        Self(error)
+    }
+}
+
+impl Display for InjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl ResponseError for InjectError {}
+
+/// A middleware that creates a child container for every request
+#[derive(Clone, Default)]
+pub struct RequestScope {
+    parent: Option<ServiceContainer>,
+    bridges: Vec<DataBridge>,
+}
+
+impl RequestScope {
+    /// Request containers will be children of the implicit container,
+    /// or proxies of the global container when none is installed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request containers will be children of the provided container
+    pub fn with_parent(parent: ServiceContainer) -> Self {
+        Self {
+            parent: Some(parent),
+            bridges: Vec::new(),
+        }
+    }
+
+    /// Registers the app's `web::Data<T>` in every request container as `Service<T>`
+    ///
+    /// Nothing is registered when the app does not have a `web::Data<T>`
+    pub fn bridge_data<T: Send + Sync + 'static>(mut self) -> Self {
+        self.bridges.push(Arc::new(|request, container| {
+            let data = request.app_data::<web::Data<T>>().cloned();
+            Box::pin(async move {
+                if let Some(data) = data {
+                    container.set_type::<Service<T>>(data.into_inner()).await;
+                }
+            })
+        }));
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestScope
+where
+    S: ActixService<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestScopeMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestScopeMiddleware {
+            service: Rc::new(service),
+            parent: self.parent.clone(),
+            bridges: self.bridges.clone(),
+        }))
+    }
+}
+
+/// The service created by the `RequestScope` middleware
+pub struct RequestScopeMiddleware<S> {
+    service: Rc<S>,
+    parent: Option<ServiceContainer>,
+    bridges: Vec<DataBridge>,
+}
+
+impl<S, B> ActixService<ServiceRequest> for RequestScopeMiddleware<S>
+where
+    S: ActixService<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    ::actix_web::dev::forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let parent = self.parent.clone();
+        let bridges = self.bridges.clone();
+
+        Box::pin(async move {
+            let container = current::request_scope(parent.as_ref());
+            container
+                .set_type::<Method>(request.method().clone())
+                .await
+                .set_type::<Uri>(request.uri().clone())
+                .await
+                .set_type::<Version>(request.version())
+                .await
+                .set_type::<HeaderMap>(request.headers().clone())
+                .await;
+            for bridge in &bridges {
+                bridge(&request, container.clone()).await;
+            }
+
+            request.extensions_mut().insert(container.clone());
+
+            current::with_container(container, async move { service.call(request).await }).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ::actix_web::{App, http::StatusCode, test, web};
+
+    use super::*;
+    use crate::helpers;
+
+    #[derive(Debug, Clone)]
+    struct Greeting(&'static str);
+
+    struct Counter(u32);
+
+    #[actix_web::test]
+    async fn test_inject_from_request_scope() {
+        let root = ServiceContainer::proxy();
+        root.set_type(Greeting("hello")).await;
+
+        let app =
+            test::init_service(
+                App::new()
+                    .wrap(RequestScope::with_parent(root).bridge_data::<Counter>())
+                    .app_data(web::Data::new(Counter(42)))
+                    .route(
+                        "/",
+                        web::get().to(
+                            |Inject(greeting): Inject<Greeting>,
+                             counter: InjectService<Counter>| async move {
+                                let method = helpers::get_type::<Method>().await.unwrap();
+                                format!("{} {} {}", greeting.0, method, counter.0.0)
+                            },
+                        ),
+                    ),
+            )
+            .await;
+
+        let body =
+            test::call_and_read_body(&app, test::TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(body, "hello GET 42");
+    }
+
+    #[actix_web::test]
+    async fn test_inject_error_names_type() {
+        struct Unknown;
+
+        let app = test::init_service(App::new().wrap(RequestScope::new()).route(
+            "/",
+            web::get().to(|_: InjectService<Unknown>| async { "unreachable" }),
+        ))
+        .await;
+
+        let response =
+            test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = test::read_body(response).await;
+        assert!(String::from_utf8_lossy(&body).contains("Unknown"));
+    }
+
+    #[actix_web::test]
+    async fn test_scope_is_per_request() {
+        let app = test::init_service(
+            App::new()
+                .wrap(RequestScope::with_parent(ServiceContainer::proxy()))
+                .route(
+                    "/",
+                    web::get().to(|| async {
+                        let count = helpers::get_type::<u8>().await.unwrap_or_default();
+                        helpers::set_type(count + 1).await;
+                        count.to_string()
+                    }),
+                ),
+        )
+        .await;
+
+        for _ in 0..2 {
+            let body =
+                test::call_and_read_body(&app, test::TestRequest::get().uri("/").to_request())
+                    .await;
+            assert_eq!(body, "0");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_scope_defaults_to_implicit_container() {
+        let tenant = ServiceContainer::proxy();
+        tenant.set_type(Greeting("tenant")).await;
+
+        let app = test::init_service(App::new().wrap(RequestScope::new()).route(
+            "/",
+            web::get().to(|Inject(greeting): Inject<Greeting>| async move {
+                let scope = helpers::service_container();
+                format!("{} {}", greeting.0, scope.parent().is_some())
+            }),
+        ))
+        .await;
+
+        let body = crate::with_container(
+            tenant,
+            test::call_and_read_body(&app, test::TestRequest::get().uri("/").to_request()),
+        )
+        .await;
+        assert_eq!(body, "tenant true");
+    }
+}
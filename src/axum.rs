@@ -0,0 +1,313 @@
+//! Axum integration
+//!
+//! `RequestScopeLayer` creates a container for every request and seeds it with the
+//! request's method, uri, version, headers and matched path. It is a child of the
+//! configured parent, otherwise of the implicit container or a proxy of the global
+//! container. Resolvers of its ancestors are given the request container, so they
+//! can use the request values.
+//! The `Inject<T>` and `InjectService<T>` extractors resolve their values from that
+//! container and `helpers::*` functions called while handling the request use it as well.
+
+use std::{
+    fmt::Display,
+    ops::{Deref, DerefMut},
+    task::{Context, Poll},
+};
+
+use ::axum::{
+    extract::{FromRequestParts, MatchedPath},
+    http::{HeaderMap, Method, Request, StatusCode, Uri, Version, request::Parts},
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+use tower_layer::Layer;
+use tower_service::Service as TowerService;
+
+use crate::{ResolveError, Service, ServiceContainer, current, helpers::service_container};
+
+/// Extracts an instance of `T` from the request's container
+///
+/// Falls back to the implicit container when the request was not handled
+/// by `RequestScopeLayer`
+#[derive(Debug, Clone)]
+pub struct Inject<T>(pub T);
+
+/// Extracts an instance of `T` wrapped in a `Service<T>` from the request's container
+#[derive(Debug, Clone)]
+pub struct InjectService<T>(pub Service<T>);
+
+/// Returns the container of the request
+pub fn request_container(parts: &Parts) -> ServiceContainer {
+    parts
+        .extensions
+        .get::<ServiceContainer>()
+        .cloned()
+        .unwrap_or_else(service_container)
+}
+
+impl<T, S> FromRequestParts<S> for Inject<T>
+where
+    T: Clone + Send + Sync + 'static,
+    S: Send + Sync,
+{
+    type Rejection = InjectRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(request_container(parts).try_get_type::<T>().await?))
+    }
+}
+
+impl<T, S> FromRequestParts<S> for InjectService<T>
+where
+    T: Send + Sync + 'static,
+    S: Send + Sync,
+{
+    type Rejection = InjectRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(
+            request_container(parts)
+                .try_get_type::<Service<T>>()
+                .await?,
+        ))
+    }
+}
+
+impl<T> Deref for Inject<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Inject<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T> Deref for InjectService<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Returned when an extractor could not resolve its type
+#[derive(Debug)]
+pub struct InjectRejection(pub ResolveError);
+
+impl From<ResolveError> for InjectRejection {
+    fn from(error: ResolveError) -> Self {
+        Self(error)
+    }
+}
+
+impl Display for InjectRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl IntoResponse for InjectRejection {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+/// A tower layer that creates a child container for every request
+#[derive(Debug, Clone, Default)]
+pub struct RequestScopeLayer {
+    parent: Option<ServiceContainer>,
+}
+
+impl RequestScopeLayer {
+    /// Request containers will be children of the implicit container,
+    /// or proxies of the global container when none is installed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request containers will be children of the provided container
+    pub fn with_parent(parent: ServiceContainer) -> Self {
+        Self {
+            parent: Some(parent),
+        }
+    }
+}
+
+impl<S> Layer<S> for RequestScopeLayer {
+    type Service = RequestScope<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestScope {
+            inner,
+            parent: self.parent.clone(),
+        }
+    }
+}
+
+/// The service created by `RequestScopeLayer`
+#[derive(Debug, Clone)]
+pub struct RequestScope<S> {
+    inner: S,
+    parent: Option<ServiceContainer>,
+}
+
+impl<S, B> TowerService<Request<B>> for RequestScope<S>
+where
+    S: TowerService<Request<B>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // The clone may not be ready, keep the service that was polled
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let parent = self.parent.clone();
+
+        Box::pin(async move {
+            let container = current::request_scope(parent.as_ref());
+            let (mut parts, body) = request.into_parts();
+
+            container
+                .set_type::<Method>(parts.method.clone())
+                .await
+                .set_type::<Uri>(parts.uri.clone())
+                .await
+                .set_type::<Version>(parts.version)
+                .await
+                .set_type::<HeaderMap>(parts.headers.clone())
+                .await;
+            if let Some(path) = parts.extensions.get::<MatchedPath>() {
+                container.set_type(path.clone()).await;
+            }
+
+            parts.extensions.insert(container.clone());
+            let request = Request::from_parts(parts, body);
+
+            current::with_container(container, async move { inner.call(request).await }).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ::axum::{Router, body::Body, routing::get};
+
+    use super::*;
+    use crate::helpers;
+
+    #[derive(Debug, Clone)]
+    struct Greeting(&'static str);
+
+    async fn send(app: Router, uri: &str) -> (StatusCode, String) {
+        let mut service = app.into_service::<Body>();
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = service.call(request).await.unwrap();
+        let status = response.status();
+        let body = ::axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_inject_from_request_scope() {
+        let root = ServiceContainer::proxy();
+        root.set_type(Greeting("hello")).await;
+        root.set(42_u64).await;
+
+        let app = Router::new()
+            .route(
+                "/users/{id}",
+                get(
+                    |Inject(greeting): Inject<Greeting>,
+                     Inject(path): Inject<MatchedPath>,
+                     answer: InjectService<u64>| async move {
+                        let method = helpers::get_type::<Method>().await.unwrap();
+                        format!("{} {} {} {}", greeting.0, method, path.as_str(), *answer)
+                    },
+                ),
+            )
+            .layer(RequestScopeLayer::with_parent(root));
+
+        let (status, body) = send(app, "/users/1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "hello GET /users/{id} 42");
+    }
+
+    #[tokio::test]
+    async fn test_inject_rejection_names_type() {
+        struct Unknown;
+
+        let app = Router::new()
+            .route(
+                "/",
+                get(|_: InjectService<Unknown>| async { "unreachable" }),
+            )
+            .layer(RequestScopeLayer::new());
+
+        let (status, body) = send(app, "/").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body.contains("Unknown"));
+    }
+
+    #[tokio::test]
+    async fn test_scope_is_per_request() {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async {
+                    let count = helpers::get_type::<u8>().await.unwrap_or_default();
+                    helpers::set_type(count + 1).await;
+                    count.to_string()
+                }),
+            )
+            .layer(RequestScopeLayer::with_parent(ServiceContainer::proxy()));
+
+        assert_eq!(send(app.clone(), "/").await.1, "0");
+        assert_eq!(send(app, "/").await.1, "0");
+    }
+
+    #[tokio::test]
+    async fn test_scope_defaults_to_implicit_container() {
+        let tenant = ServiceContainer::proxy();
+        tenant.set_type(Greeting("tenant")).await;
+
+        let app = Router::new()
+            .route(
+                "/",
+                get(|Inject(greeting): Inject<Greeting>| async move { greeting.0 }),
+            )
+            .layer(RequestScopeLayer::new());
+
+        let (_, body) = crate::with_container(tenant, send(app, "/")).await;
+        assert_eq!(body, "tenant");
+    }
+
+    #[tokio::test]
+    async fn test_inner_service_is_called_within_scope() {
+        let mut service =
+            RequestScopeLayer::new().layer(::tower::service_fn(|request: Request<Body>| {
+                let implicit = helpers::service_container();
+                async move {
+                    let scope = request.extensions().get::<ServiceContainer>().unwrap();
+                    Ok::<_, std::convert::Infallible>(scope.id() == implicit.id())
+                }
+            }));
+
+        assert!(service.call(Request::new(Body::empty())).await.unwrap());
+    }
+}
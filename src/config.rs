@@ -0,0 +1,361 @@
+//! Configuration loaded from a file and reloaded when the file changes
+//!
+//! `FileConfigProvider` parses a JSON or TOML file into `T`, registers it with
+//! `set_type` and polls the file for changes. Every version of the file that
+//! parses replaces the registered instance, so `watch::<T>()` and `Live<T>`
+//! observe it. Versions that do not parse are reported to the error hook and
+//! the last good value is kept.
+//!
+//! Versions are told apart by a hash of the content, so edits keeping the size
+//! and the modification time of the file are seen, and touching the file without
+//! changing it does not reload it.
+//!
+//! ```rust,no_run
+//! # use busybody::{*, config::*};
+//! # use std::time::Duration;
+//! #[derive(Debug, Clone, serde::Deserialize)]
+//! struct Settings {
+//!     hostname: String,
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), ConfigError> {
+//! let reloader = FileConfigProvider::<Settings>::new("settings.toml")
+//!     .poll_interval(Duration::from_secs(5))
+//!     .on_error(|e| eprintln!("settings were not reloaded: {e}"))
+//!     .register(&helpers::service_container())
+//!     .await?;
+//!
+//! let mut settings = helpers::watch::<Settings>().await;
+//! while settings.changed().await.is_ok() {
+//!     println!("hostname: {}", settings.get().unwrap().hostname);
+//! }
+//! # drop(reloader);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt::Display,
+    hash::{DefaultHasher, Hash, Hasher},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::de::DeserializeOwned;
+
+use crate::{ServiceContainer, runtime};
+
+/// The formats a configuration file can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Returns the format matching the extension of the file
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// Parses the content of a file
+    pub fn parse<T: DeserializeOwned>(&self, content: &str) -> Result<T, String> {
+        match self {
+            Self::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+            Self::Toml => toml::from_str(content).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Errors returned when a configuration file cannot be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The format could not be inferred from the extension of the file
+    UnknownFormat { path: PathBuf },
+    /// The file could not be read
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// The content of the file could not be parsed
+    Parse { path: PathBuf, message: String },
+}
+
+impl ConfigError {
+    /// The path of the file that could not be loaded
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::UnknownFormat { path } | Self::Io { path, .. } | Self::Parse { path, .. } => path,
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownFormat { path } => {
+                write!(f, "unknown configuration format: {}", path.display())
+            }
+            Self::Io { path, error } => write!(f, "could not read {}: {error}", path.display()),
+            Self::Parse { path, message } => {
+                write!(f, "could not parse {}: {message}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+type ErrorHook = Arc<dyn Fn(&ConfigError) + Send + Sync>;
+
+/// Loads `T` from a file and keeps it registered while the file changes
+pub struct FileConfigProvider<T> {
+    path: PathBuf,
+    format: Option<ConfigFormat>,
+    interval: Duration,
+    on_error: Option<ErrorHook>,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned + Clone + Send + Sync + 'static> FileConfigProvider<T> {
+    /// The format is inferred from the extension of the file.
+    /// The file is checked for changes every second
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            format: None,
+            interval: Duration::from_secs(1),
+            on_error: None,
+            _type: PhantomData,
+        }
+    }
+
+    /// Sets the format instead of inferring it from the extension
+    pub fn format(mut self, format: ConfigFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Sets how often the file is checked for changes
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Called each time a change to the file cannot be loaded
+    pub fn on_error(mut self, hook: impl Fn(&ConfigError) + Send + Sync + 'static) -> Self {
+        self.on_error = Some(Arc::new(hook));
+        self
+    }
+
+    /// Reads and parses the file
+    pub fn load(&self) -> Result<T, ConfigError> {
+        self.parse(&self.read()?)
+    }
+
+    /// Reads the file once its format is known
+    fn read(&self) -> Result<String, ConfigError> {
+        self.content_format()?;
+        std::fs::read_to_string(&self.path).map_err(|error| ConfigError::Io {
+            path: self.path.clone(),
+            error,
+        })
+    }
+
+    fn parse(&self, content: &str) -> Result<T, ConfigError> {
+        self.content_format()?
+            .parse(content)
+            .map_err(|message| ConfigError::Parse {
+                path: self.path.clone(),
+                message,
+            })
+    }
+
+    fn content_format(&self) -> Result<ConfigFormat, ConfigError> {
+        self.format
+            .or_else(|| ConfigFormat::from_path(&self.path))
+            .ok_or_else(|| ConfigError::UnknownFormat {
+                path: self.path.clone(),
+            })
+    }
+
+    /// Loads the file, registers `T` in the container and starts watching the file
+    ///
+    /// The file is watched until the returned reloader is dropped
+    pub async fn register(
+        self,
+        container: &ServiceContainer,
+    ) -> Result<ConfigReloader, ConfigError> {
+        // The version is taken from the content that was parsed,
+        // so a change made while loading is picked up by the next poll
+        let content = self.read()?;
+        let mut version = Some(fingerprint(&content));
+        container.set_type(self.parse(&content)?).await;
+
+        let reloader = ConfigReloader::default();
+        let stopped = reloader.stopped.clone();
+        let container = container.clone();
+        runtime::spawn_detached(async move {
+            loop {
+                runtime::sleep(self.interval).await;
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let content = self.read();
+                let current = content.as_deref().ok().map(fingerprint);
+                if current == version {
+                    continue;
+                }
+                version = current;
+
+                match content.and_then(|content| self.parse(&content)) {
+                    Ok(value) => {
+                        container.set_type(value).await;
+                    }
+                    Err(e) => {
+                        if let Some(hook) = &self.on_error {
+                            hook(&e);
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(reloader)
+    }
+}
+
+/// Identifies a version of the file by its content
+fn fingerprint(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Stops watching the file when dropped. See `FileConfigProvider::register`
+#[derive(Debug, Default)]
+#[must_use = "the file is no longer watched once the reloader is dropped"]
+pub struct ConfigReloader {
+    stopped: Arc<AtomicBool>,
+}
+
+impl ConfigReloader {
+    /// Stops watching the file. The registered value is kept
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for ConfigReloader {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    struct Settings {
+        hostname: String,
+        port: u16,
+    }
+
+    fn temp_file(extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("busybody-{}.{extension}", ulid::Ulid::new()))
+    }
+
+    #[test]
+    fn test_load_formats() {
+        let path = temp_file("toml");
+        std::fs::write(&path, "hostname = \"localhost\"\nport = 80\n").unwrap();
+        let settings = FileConfigProvider::<Settings>::new(&path).load().unwrap();
+        assert_eq!(settings.port, 80);
+
+        std::fs::write(&path, r#"{"hostname": "localhost", "port": 81}"#).unwrap();
+        let provider = FileConfigProvider::<Settings>::new(&path).format(ConfigFormat::Json);
+        assert_eq!(provider.load().unwrap().port, 81);
+        assert!(matches!(
+            FileConfigProvider::<Settings>::new(&path).load(),
+            Err(ConfigError::Parse { .. })
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(provider.load(), Err(ConfigError::Io { .. })));
+        assert!(matches!(
+            FileConfigProvider::<Settings>::new("settings.ini").load(),
+            Err(ConfigError::UnknownFormat { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let path = temp_file("json");
+        std::fs::write(&path, r#"{"hostname": "a", "port": 1}"#).unwrap();
+
+        let errors = Arc::new(AtomicUsize::new(0));
+        let container = ServiceContainer::proxy();
+        let reloader = FileConfigProvider::<Settings>::new(&path)
+            .poll_interval(Duration::from_millis(5))
+            .on_error({
+                let errors = errors.clone();
+                move |_| {
+                    errors.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .register(&container)
+            .await
+            .unwrap();
+
+        let mut watch = container.watch::<Settings>().await;
+        assert_eq!(watch.get().unwrap().hostname, "a");
+
+        std::fs::write(&path, r#"{"hostname": "bb", "port": 2}"#).unwrap();
+        runtime::timeout(Duration::from_secs(5), watch.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(container.get_type::<Settings>().await.unwrap().port, 2);
+
+        // Same length, possibly the same modification time
+        std::fs::write(&path, r#"{"hostname": "cc", "port": 3}"#).unwrap();
+        runtime::timeout(Duration::from_secs(5), watch.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(watch.get().unwrap().port, 3);
+
+        std::fs::write(&path, r#"{"hostname": "#).unwrap();
+        while errors.load(Ordering::SeqCst) == 0 {
+            runtime::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(container.get_type::<Settings>().await.unwrap().port, 3);
+        assert!(!watch.has_changed());
+
+        drop(reloader);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,123 +1,443 @@
 #![allow(dead_code)]
 
 use futures::future::BoxFuture;
-use tokio::sync::{Mutex, RwLock};
 
-use crate::{Handler, Resolver, helpers::service_container, service::Service};
+use crate::{
+    Handler, Resolver, current,
+    error::ResolveError,
+    metrics::{Layer, Metrics, Stopwatch},
+    pool::{Pool, PoolOptions},
+    registration::{Forgotten, Presence, Registration, RegistrationKind, Removed},
+    runtime::{self, Mutex, RwLock},
+    service::Service,
+    snapshot::Snapshot,
+    state::State,
+    task::{TaskScope, TaskScopeInfo},
+    telemetry,
+    ttl::{Invalidate, TtlCache, TtlFactory},
+    watch::{Channel, Publisher, Watch},
+};
 use std::{
-    any::{Any, TypeId},
-    collections::HashMap,
-    fmt::{Debug, Display},
-    sync::{Arc, OnceLock, atomic::AtomicUsize},
+    any::{Any, TypeId, type_name},
+    collections::{HashMap, HashSet},
+    fmt::{Debug, Display, Write},
+    sync::{
+        Arc, OnceLock, PoisonError, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
 };
 
 pub(crate) static SERVICE_CONTAINER: OnceLock<ServiceContainer> = OnceLock::new();
 pub(crate) const GLOBAL_INSTANCE_ID: u64 = 0;
-pub(crate) static TASK_SERVICE_CONTAINER: OnceLock<
-    std::sync::Mutex<HashMap<u64, (AtomicUsize, Container)>>,
-> = OnceLock::new();
-
-type ResolverCollection = HashMap<
-    TypeId,
-    Arc<
-        Mutex<
-            Box<
-                dyn FnMut(
-                        ServiceContainer,
-                    )
-                        -> BoxFuture<'static, Box<dyn Any + Send + Sync + 'static>>
-                    + Sync
-                    + Send
-                    + 'static,
-            >,
-        >,
-    >,
+/// Returns the process wide service container
+pub(crate) fn global_container() -> ServiceContainer {
+    SERVICE_CONTAINER.get_or_init(ServiceContainer::new).clone()
+}
+
+type ResolverFn = Box<
+    dyn FnMut(ServiceContainer) -> BoxFuture<'static, Box<dyn Any + Send + Sync + 'static>>
+        + Sync
+        + Send
+        + 'static,
 >;
 
+type ResolverCollection = HashMap<TypeId, ResolverEntry>;
+
+type Value = Arc<dyn Any + Send + Sync + 'static>;
+
+type WatcherCollection = HashMap<TypeId, Arc<dyn Publisher>>;
+
+/// Bookkeeping stored alongside every registration
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EntryMeta {
+    pub(crate) type_name: &'static str,
+    pub(crate) kind: RegistrationKind,
+    pub(crate) order: u64,
+}
+
+#[derive(Clone)]
+pub(crate) struct ServiceEntry {
+    value: Value,
+    meta: EntryMeta,
+}
+
+/// Where the values of a resolver entry come from
+#[derive(Clone)]
+enum Source {
+    /// A value registered with `set_type`, copies of it are handed out
+    Value(Value),
+    /// A closure producing the values
+    Callback(Arc<Mutex<ResolverFn>>),
+}
+
+#[derive(Clone)]
+pub(crate) struct ResolverEntry {
+    source: Source,
+    meta: EntryMeta,
+    timeout: Option<Duration>,
+    cache: Option<Arc<dyn Invalidate>>,
+}
+
 #[derive(Default, Clone)]
 pub(crate) struct Container {
-    services: Arc<RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync + 'static>>>>,
+    services: Arc<RwLock<HashMap<TypeId, ServiceEntry>>>,
     resolvers: Arc<RwLock<ResolverCollection>>,
+    sequence: Arc<AtomicU64>,
+    default_timeout: Arc<std::sync::RwLock<Option<Duration>>>,
+    watchers: Arc<std::sync::Mutex<WatcherCollection>>,
+    metrics: Metrics,
 }
 
 impl Container {
+    fn next_meta<T: 'static>(&self, kind: RegistrationKind) -> EntryMeta {
+        EntryMeta {
+            type_name: type_name::<T>(),
+            kind,
+            order: self.sequence.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
     pub(crate) async fn get<T: Clone + 'static>(&self, ci: ServiceContainer) -> Option<T> {
+        self.try_get(ci).await.ok().flatten()
+    }
+
+    pub(crate) async fn try_get<T: Clone + 'static>(
+        &self,
+        ci: ServiceContainer,
+    ) -> Result<Option<T>, ResolveError> {
         let lock = self.services.read().await;
-        if let Some(raw) = lock.get(&TypeId::of::<T>()) {
-            return raw.downcast_ref().cloned();
+        if let Some(entry) = lock.get(&TypeId::of::<T>()) {
+            telemetry::record_source(None);
+            return Ok(entry.value.downcast_ref().cloned());
         }
         drop(lock);
 
         let lock = self.resolvers.read().await;
 
-        if let Some(mutex) = lock.get(&TypeId::of::<T>()).cloned() {
+        if let Some(entry) = lock.get(&TypeId::of::<T>()).cloned() {
             drop(lock);
-            let mut callback = mutex.lock().await;
-            return callback(ci).await.downcast_ref::<T>().cloned();
+            let kind = entry.meta.kind;
+            telemetry::record_source(Some(kind));
+
+            let callback = match entry.source {
+                Source::Value(value) => return Ok(value.downcast_ref::<T>().cloned()),
+                Source::Callback(callback) => callback,
+            };
+            let call = async {
+                let mut callback = callback.lock().await;
+                callback(ci).await
+            };
+
+            let watch = Stopwatch::start();
+            let value = match entry.timeout.or_else(|| self.default_timeout()) {
+                Some(timeout) => {
+                    telemetry::resolver(entry.meta.type_name, kind, runtime::timeout(timeout, call))
+                        .await
+                        .map_err(|_| ResolveError::Timeout {
+                            type_name: entry.meta.type_name,
+                            timeout,
+                        })?
+                }
+                None => telemetry::resolver(entry.meta.type_name, kind, call).await,
+            };
+            self.metrics.record_resolver::<T>(watch);
+
+            return Ok(value.downcast_ref::<T>().cloned());
         }
 
-        None
+        Ok(None)
+    }
+
+    pub(crate) fn default_timeout(&self) -> Option<Duration> {
+        self.default_timeout
+            .read()
+            .ok()
+            .and_then(|timeout| *timeout)
+    }
+
+    pub(crate) fn set_default_timeout(&self, timeout: Option<Duration>) {
+        if let Ok(mut lock) = self.default_timeout.write() {
+            *lock = timeout;
+        }
     }
 
     pub(crate) async fn set<T: Send + Sync + 'static>(&self, value: T) -> &Self {
+        let value: Value = Arc::new(value);
         let mut lock = self.services.write().await;
         lock.insert(
             TypeId::of::<T>(),
-            Box::new(value) as Box<dyn Any + Send + Sync + 'static>,
+            ServiceEntry {
+                value: value.clone(),
+                meta: self.next_meta::<T>(RegistrationKind::Instance),
+            },
         );
+        // Publishing under the lock keeps watchers in the order the values were stored
+        self.publish::<T>(Some(&*value));
         drop(lock);
 
         self
     }
 
-    pub(crate) async fn forget<T: 'static>(&self, ci: ServiceContainer) -> Option<Box<T>> {
-        let mut lock = self.services.write().await;
-        if let Some(raw) = lock.remove(&TypeId::of::<T>()) {
-            self.resolvers.write().await.remove(&TypeId::of::<T>());
-            return raw.downcast().ok();
+    /// Stores the value as an instance registration among the resolvers,
+    /// copies of it are handed out
+    pub(crate) async fn set_value<T: Clone + Send + Sync + 'static>(&self, value: T) -> &Self {
+        let value: Value = Arc::new(value);
+        let mut lock = self.resolvers.write().await;
+        lock.insert(
+            TypeId::of::<T>(),
+            ResolverEntry {
+                source: Source::Value(value.clone()),
+                meta: self.next_meta::<T>(RegistrationKind::Instance),
+                timeout: None,
+                cache: None,
+            },
+        );
+        self.publish::<T>(Some(&*value));
+        drop(lock);
+
+        self
+    }
+
+    /// Same as `set` but gives up when the services are locked
+    pub(crate) fn try_set<T: Send + Sync + 'static>(&self, value: T) -> bool {
+        let Some(mut lock) = self.services.try_write() else {
+            return false;
+        };
+        let value: Value = Arc::new(value);
+        lock.insert(
+            TypeId::of::<T>(),
+            ServiceEntry {
+                value: value.clone(),
+                meta: self.next_meta::<T>(RegistrationKind::Instance),
+            },
+        );
+        self.publish::<T>(Some(&*value));
+        drop(lock);
+
+        true
+    }
+
+    /// Replaces the stored instance with a modified copy while both maps are locked.
+    /// The closure is handed back when the instance does not exist in this layer
+    pub(crate) async fn update<T: Clone + Send + Sync + 'static, R, F: FnOnce(&mut T) -> R>(
+        &self,
+        callback: F,
+    ) -> Result<R, F> {
+        let type_id = TypeId::of::<T>();
+        let mut services = self.services.write().await;
+        let mut resolvers = self.resolvers.write().await;
+
+        if let Some(entry) = services.get_mut(&type_id)
+            && let Some(value) = entry.value.downcast_ref::<T>()
+        {
+            // Snapshots may share the current value, it is replaced instead of modified
+            let mut value = value.clone();
+            let result = callback(&mut value);
+            self.publish::<T>(Some(&value));
+            entry.value = Arc::new(value);
+            return Ok(result);
         }
 
-        let mut lock = self.resolvers.write().await;
-        if let Some(mutex) = lock.remove(&TypeId::of::<T>()) {
-            drop(lock);
-            let mut callback = mutex.lock().await;
-            return callback(ci).await.downcast::<T>().ok();
+        let Some(entry) = resolvers.get_mut(&type_id) else {
+            return Err(callback);
+        };
+        let Source::Value(value) = &entry.source else {
+            return Err(callback);
+        };
+        let Some(value) = value.downcast_ref::<T>() else {
+            return Err(callback);
+        };
+
+        let mut value = value.clone();
+        let result = callback(&mut value);
+        self.publish::<T>(Some(&value));
+        entry.source = Source::Value(Arc::new(value));
+
+        Ok(result)
+    }
+
+    /// Returns the stored instance of the type, without calling resolvers
+    pub(crate) async fn instance(&self, type_id: TypeId) -> Option<Value> {
+        let services = self.services.read().await;
+        let resolvers = self.resolvers.read().await;
+        instance_of(&services, &resolvers, type_id)
+    }
+
+    /// Removes the instance and the resolver of the type without calling the resolver
+    pub(crate) async fn forget<T: Send + Sync + 'static>(&self) -> Forgotten<T> {
+        let type_id = TypeId::of::<T>();
+        let mut services = self.services.write().await;
+        let mut resolvers = self.resolvers.write().await;
+        let service = services.remove(&type_id);
+        let resolver = resolvers.remove(&type_id);
+        drop(services);
+        drop(resolvers);
+
+        let (stored, removed_resolver) = match resolver.map(|entry| entry.source) {
+            Some(Source::Value(value)) => (Some(value), false),
+            Some(Source::Callback(_)) => (None, true),
+            None => (None, false),
+        };
+        let instance = service
+            .map(|entry| entry.value)
+            .or(stored)
+            .and_then(|value| Removed::new(value));
+        if instance.is_some() {
+            self.publish::<T>(None);
         }
 
-        None
+        Forgotten::new(instance, removed_resolver)
+    }
+
+    /// Removes every registration matching the predicate from this layer.
+    /// Returns the number of registrations removed
+    pub(crate) async fn remove_where(&self, predicate: impl Fn(&EntryMeta) -> bool) -> usize {
+        let mut services = self.services.write().await;
+        let mut resolvers = self.resolvers.write().await;
+
+        let mut removed = 0;
+        let mut instances = Vec::new();
+        services.retain(|type_id, entry| {
+            let remove = predicate(&entry.meta);
+            if remove {
+                removed += 1;
+                instances.push(*type_id);
+            }
+            !remove
+        });
+        resolvers.retain(|type_id, entry| {
+            let remove = predicate(&entry.meta);
+            if remove {
+                removed += 1;
+                if entry.meta.kind == RegistrationKind::Instance {
+                    instances.push(*type_id);
+                }
+            }
+            !remove
+        });
+        drop(services);
+        drop(resolvers);
+
+        for type_id in instances {
+            self.publish_id(type_id, None);
+        }
+
+        removed
     }
 
     pub(crate) async fn remove_resolver<T: 'static>(&self) -> bool {
-        if self.has_resolver::<T>().await {
-            let mut lock = self.resolvers.write().await;
-            lock.remove(&TypeId::of::<T>());
-            true
-        } else {
-            false
+        let type_id = TypeId::of::<T>();
+        let services = self.services.read().await;
+        let mut resolvers = self.resolvers.write().await;
+        let removed = resolvers.remove(&type_id);
+        self.publish_replaced(&services, type_id, removed.as_ref());
+
+        removed.is_some()
+    }
+
+    /// Notifies the watchers of the type when the resolver entry replaced or
+    /// removed held the instance lookups were finding
+    fn publish_replaced(
+        &self,
+        services: &HashMap<TypeId, ServiceEntry>,
+        type_id: TypeId,
+        replaced: Option<&ResolverEntry>,
+    ) {
+        if replaced.is_some_and(|entry| matches!(entry.source, Source::Value(_)))
+            && !services.contains_key(&type_id)
+        {
+            self.publish_id(type_id, None);
         }
     }
 
     pub(crate) async fn resolver<T: Send + Sync + 'static, F>(
         &self,
+        kind: RegistrationKind,
+        callback: impl FnMut(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.resolver_with_timeout(kind, None, callback).await
+    }
+
+    pub(crate) async fn resolver_with_timeout<T: Send + Sync + 'static, F>(
+        &self,
+        kind: RegistrationKind,
+        timeout: Option<Duration>,
+        callback: impl FnMut(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.insert_resolver(kind, timeout, None, callback).await
+    }
+
+    async fn insert_resolver<T: Send + Sync + 'static, F>(
+        &self,
+        kind: RegistrationKind,
+        timeout: Option<Duration>,
+        cache: Option<Arc<dyn Invalidate>>,
         mut callback: impl FnMut(ServiceContainer) -> F + Send + Sync + 'static,
     ) -> &Self
     where
         F: Future<Output = T> + Send + 'static,
     {
+        let services = self.services.read().await;
         let mut lock = self.resolvers.write().await;
-        lock.insert(
+        let replaced = lock.insert(
             TypeId::of::<T>(),
-            Arc::new(Mutex::new(Box::new(move |c| {
-                let f = (callback)(c);
-                Box::pin(async move {
-                    //
-                    Box::new(f.await) as Box<dyn Any + Send + Sync + 'static>
-                })
-            }))),
+            ResolverEntry {
+                source: Source::Callback(Arc::new(Mutex::new(Box::new(move |c| {
+                    let f = (callback)(c);
+                    Box::pin(async move {
+                        //
+                        Box::new(f.await) as Box<dyn Any + Send + Sync + 'static>
+                    })
+                })))),
+                meta: self.next_meta::<T>(kind),
+                timeout,
+                cache,
+            },
         );
+        self.publish_replaced(&services, TypeId::of::<T>(), replaced.as_ref());
+
         self
     }
 
+    /// Registers a resolver whose values are cached for the duration
+    pub(crate) async fn ttl_resolver<T: Clone + Send + Sync + 'static>(
+        &self,
+        cache: TtlCache<T>,
+        factory: Arc<TtlFactory<T>>,
+    ) -> &Self {
+        let cache = Arc::new(cache);
+        self.insert_resolver(
+            RegistrationKind::ResolverTtl,
+            None,
+            Some(cache.clone()),
+            move |c| cache.clone().get(c, factory.clone()),
+        )
+        .await
+    }
+
+    /// Empties the cache of the resolver of the type.
+    /// Returns false when the type has no cached resolver in this layer
+    pub(crate) async fn invalidate<T: 'static>(&self) -> bool {
+        let lock = self.resolvers.read().await;
+        match lock
+            .get(&TypeId::of::<T>())
+            .and_then(|entry| entry.cache.as_ref())
+        {
+            Some(cache) => {
+                cache.invalidate();
+                true
+            }
+            None => false,
+        }
+    }
+
     pub(crate) async fn soft_resolver<T: Clone + Send + Sync + 'static, F>(
         &self,
         callback: impl FnMut(ServiceContainer) -> F + Send + Sync + 'static,
@@ -129,21 +449,275 @@ impl Container {
             return self;
         }
 
-        self.resolver(callback).await
+        self.resolver(RegistrationKind::SoftResolver, callback)
+            .await
     }
 
     pub(crate) async fn has_resolver<T: 'static>(&self) -> bool {
         let lock = self.resolvers.read().await;
         lock.get(&TypeId::of::<T>()).is_some()
     }
+
+    pub(crate) async fn has_instance<T: 'static>(&self) -> bool {
+        self.services.read().await.contains_key(&TypeId::of::<T>())
+    }
+
+    pub(crate) async fn presence<T: 'static>(&self) -> Presence {
+        if self.has_instance::<T>().await {
+            return Presence::Stored;
+        }
+
+        match self.resolvers.read().await.get(&TypeId::of::<T>()) {
+            Some(entry) if entry.meta.kind == RegistrationKind::Instance => Presence::Stored,
+            Some(_) => Presence::Resolvable,
+            None => Presence::Absent,
+        }
+    }
+
+    /// Returns the bookkeeping of every registration in this layer
+    pub(crate) async fn entries(&self) -> Vec<EntryMeta> {
+        let mut entries = self
+            .services
+            .read()
+            .await
+            .values()
+            .map(|entry| entry.meta)
+            .collect::<Vec<_>>();
+        entries.extend(self.resolvers.read().await.values().map(|entry| entry.meta));
+        entries.sort_by_key(|meta| meta.order);
+
+        entries
+    }
+
+    /// Returns a copy of the registrations in this layer.
+    /// Instances and resolvers are shared, not cloned
+    pub(crate) async fn snapshot(&self) -> Registrations {
+        let services = self.services.read().await;
+        let resolvers = self.resolvers.read().await;
+
+        Registrations {
+            origin: Arc::downgrade(&self.services),
+            services: services.clone(),
+            resolvers: resolvers.clone(),
+        }
+    }
+
+    /// Replaces the registrations in this layer with the ones provided.
+    /// Watchers of every instance that changed or was removed are notified
+    ///
+    /// Returns false and changes nothing when the registrations were taken from another store
+    pub(crate) async fn restore(&self, registrations: &Registrations) -> bool {
+        if !std::ptr::eq(registrations.origin.as_ptr(), Arc::as_ptr(&self.services)) {
+            return false;
+        }
+
+        let mut services = self.services.write().await;
+        let mut resolvers = self.resolvers.write().await;
+
+        let previous = Registrations {
+            origin: registrations.origin.clone(),
+            services: std::mem::replace(&mut *services, registrations.services.clone()),
+            resolvers: std::mem::replace(&mut *resolvers, registrations.resolvers.clone()),
+        };
+
+        let type_ids = previous
+            .services
+            .keys()
+            .chain(previous.resolvers.keys())
+            .chain(registrations.services.keys())
+            .chain(registrations.resolvers.keys())
+            .copied()
+            .collect::<HashSet<_>>();
+        for type_id in type_ids {
+            let before = previous.instance(type_id);
+            let after = registrations.instance(type_id);
+            let unchanged = match (&before, &after) {
+                (Some(before), Some(after)) => Arc::ptr_eq(before, after),
+                (None, None) => true,
+                _ => false,
+            };
+            if !unchanged {
+                self.publish_id(type_id, after.as_deref());
+            }
+        }
+
+        true
+    }
+
+    /// Drops every instance and resolver stored in this layer
+    ///
+    /// When a map is locked, it is cleared by a spawned task instead
+    pub(crate) fn dispose(&self) {
+        fn clear<V: Send + Sync + 'static>(map: &Arc<RwLock<HashMap<TypeId, V>>>) {
+            if let Some(mut lock) = map.try_write() {
+                lock.clear();
+            } else {
+                let map = map.clone();
+                runtime::spawn_detached(async move { map.write().await.clear() });
+            }
+        }
+
+        clear(&self.services);
+        clear(&self.resolvers);
+
+        let watchers = std::mem::take(&mut *self.watchers());
+        for channel in watchers.values() {
+            channel.close();
+        }
+    }
+
+    fn watchers(&self) -> std::sync::MutexGuard<'_, WatcherCollection> {
+        self.watchers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the channel of the type, creating it when needed.
+    /// The flag is true when the channel was created
+    pub(crate) fn channel<T: Clone + Send + Sync + 'static>(&self) -> (Arc<Channel<T>>, bool) {
+        let mut watchers = self.watchers();
+        if let Some(channel) = watchers
+            .get(&TypeId::of::<T>())
+            .and_then(|channel| channel.clone().as_any().downcast().ok())
+        {
+            return (channel, false);
+        }
+
+        let channel = Arc::new(Channel::<T>::new());
+        watchers.insert(TypeId::of::<T>(), channel.clone());
+        (channel, true)
+    }
+
+    /// Sends the new value of the type to its receivers
+    pub(crate) fn publish<T: 'static>(&self, value: Option<&(dyn Any + Send + Sync)>) {
+        self.publish_id(TypeId::of::<T>(), value);
+    }
+
+    fn publish_id(&self, type_id: TypeId, value: Option<&(dyn Any + Send + Sync)>) {
+        let mut watchers = self.watchers();
+        if let Some(channel) = watchers.get(&type_id) {
+            // Only the collection holds the channel once every receiver is dropped
+            if Arc::strong_count(channel) == 1 {
+                watchers.remove(&type_id);
+            } else {
+                channel.publish(value);
+            }
+        }
+    }
+
+    /// Removes the instance and the resolver of the type from this layer
+    #[cfg(feature = "testing")]
+    pub(crate) async fn take<T: 'static>(&self) -> TakenEntries {
+        let type_id = TypeId::of::<T>();
+        let service = self.services.write().await.remove(&type_id);
+        let resolver = self.resolvers.write().await.remove(&type_id);
+
+        TakenEntries {
+            type_id,
+            service,
+            resolver,
+        }
+    }
+
+    /// Replaces the registrations of a type with the ones previously taken
+    ///
+    /// When a map is locked, it is updated by a spawned task instead
+    #[cfg(feature = "testing")]
+    pub(crate) fn put_back(&self, taken: TakenEntries) {
+        fn replace<V>(map: &mut HashMap<TypeId, V>, type_id: TypeId, entry: Option<V>) {
+            match entry {
+                Some(entry) => map.insert(type_id, entry),
+                None => map.remove(&type_id),
+            };
+        }
+
+        let TakenEntries {
+            type_id,
+            service,
+            resolver,
+        } = taken;
+
+        if let Some(mut services) = self.services.try_write()
+            && let Some(mut resolvers) = self.resolvers.try_write()
+        {
+            replace(&mut services, type_id, service);
+            replace(&mut resolvers, type_id, resolver);
+            self.publish_id(
+                type_id,
+                instance_of(&services, &resolvers, type_id).as_deref(),
+            );
+        } else {
+            let container = self.clone();
+            runtime::spawn_detached(async move {
+                let mut services = container.services.write().await;
+                let mut resolvers = container.resolvers.write().await;
+                replace(&mut services, type_id, service);
+                replace(&mut resolvers, type_id, resolver);
+                container.publish_id(
+                    type_id,
+                    instance_of(&services, &resolvers, type_id).as_deref(),
+                );
+            });
+        }
+    }
+}
+
+/// The registrations of a type taken out of a container layer
+#[cfg(feature = "testing")]
+pub(crate) struct TakenEntries {
+    type_id: TypeId,
+    service: Option<ServiceEntry>,
+    resolver: Option<ResolverEntry>,
+}
+
+/// The registrations of a container layer at a point in time
+#[derive(Clone)]
+pub(crate) struct Registrations {
+    /// The store the registrations were taken from, kept to recognize it
+    origin: Weak<RwLock<HashMap<TypeId, ServiceEntry>>>,
+    services: HashMap<TypeId, ServiceEntry>,
+    resolvers: ResolverCollection,
+}
+
+impl Registrations {
+    /// The instance of the type, as a lookup would find it
+    fn instance(&self, type_id: TypeId) -> Option<Value> {
+        instance_of(&self.services, &self.resolvers, type_id)
+    }
+}
+
+/// The instance of the type stored in the maps of a layer, as a lookup would find it
+fn instance_of(
+    services: &HashMap<TypeId, ServiceEntry>,
+    resolvers: &ResolverCollection,
+    type_id: TypeId,
+) -> Option<Value> {
+    if let Some(entry) = services.get(&type_id) {
+        return Some(entry.value.clone());
+    }
+
+    match resolvers.get(&type_id).map(|entry| &entry.source) {
+        Some(Source::Value(value)) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+/// Disposes the registrations of a scoped container when dropped
+pub(crate) struct ScopeGuard(Container);
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        self.0.dispose();
+    }
 }
 
 #[derive(Clone)]
 pub struct ServiceContainer {
     in_proxy_mode: bool,
-    is_task_mode: bool,
+    task_scope: Option<Arc<TaskScope>>,
     is_reference: bool,
+    is_isolated: bool,
     container: Container,
+    parent: Option<Arc<ServiceContainer>>,
+    root: Option<Arc<ServiceContainer>>,
     id: u64,
 }
 
@@ -176,24 +750,66 @@ impl ServiceContainer {
         self.id
     }
     pub(crate) fn new() -> Self {
-        _ = TASK_SERVICE_CONTAINER.get_or_init(std::sync::Mutex::default);
-
         Self {
             id: GLOBAL_INSTANCE_ID,
             in_proxy_mode: false,
-            is_task_mode: false,
+            task_scope: None,
             is_reference: false,
+            is_isolated: false,
             container: Default::default(),
+            parent: None,
+            root: None,
         }
     }
 
     pub(crate) fn make_reference(&self) -> Self {
         Self {
             is_reference: true,
-            id: self.id.clone(),
+            is_isolated: self.is_isolated,
+            id: self.id,
             in_proxy_mode: self.in_proxy_mode,
-            is_task_mode: self.is_task_mode,
+            task_scope: self.task_scope.clone(),
             container: self.container.clone(),
+            parent: self.parent.clone(),
+            root: self.root.clone(),
+        }
+    }
+
+    /// Create a root container that does not fall back to the global container
+    pub(crate) fn isolated() -> Self {
+        Self {
+            id: ulid::Ulid::new().0 as u64,
+            is_isolated: true,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if this container is an isolated root container.
+    /// See `ServiceContainerBuilder::isolated`
+    pub fn is_isolated(&self) -> bool {
+        self.is_isolated
+    }
+
+    /// The root that proxies created from this container fall back to.
+    /// None stands for the global container
+    fn proxy_root(&self) -> Option<Arc<ServiceContainer>> {
+        if self.is_isolated {
+            Some(Arc::new(self.clone()))
+        } else {
+            self.root.clone()
+        }
+    }
+
+    /// The ID of the root container. None stands for the global container
+    fn id_of_root(root: &Option<Arc<ServiceContainer>>) -> u64 {
+        root.as_ref().map_or(GLOBAL_INSTANCE_ID, |root| root.id)
+    }
+
+    /// The root container this container falls back to
+    fn root(&self) -> ServiceContainer {
+        match &self.root {
+            Some(root) => root.as_ref().clone(),
+            None => global_container(),
         }
     }
 
@@ -205,48 +821,140 @@ impl ServiceContainer {
     /// This allows a new instance of a type to be created and use in
     /// a specific scope
     pub fn proxy() -> Self {
-        let mut ci = Self::default();
-        ci.id = ulid::Ulid::new().0 as u64;
-        ci.in_proxy_mode = true;
+        Self {
+            id: ulid::Ulid::new().0 as u64,
+            in_proxy_mode: true,
+            ..Default::default()
+        }
+    }
+
+    /// Create a proxy container that falls back to the root of this container
+    ///
+    /// The root is the isolated root container this container belongs to,
+    /// or the global container
+    pub fn new_proxy(&self) -> Self {
+        let mut ci = Self::proxy();
+        ci.root = self.proxy_root();
 
         ci
     }
 
-    /// Returns a new proxy service container that is tie to the current task
+    /// Returns the proxy container of the current task for the root of this container
     ///
     /// If this method is called outside of a task context, an error will be return
-    pub fn make_task_proxy() -> Result<Self, String> {
-        if let Some(ci) = Self::get_task_instance() {
-            return Ok(ci);
-        }
+    pub fn new_task_proxy(&self) -> Result<Self, String> {
+        Self::make_task_proxy_of(self.proxy_root())
+    }
 
-        let id = if let Some(id) = tokio::task::try_id() {
-            id.to_string()
-                .parse::<u64>()
-                .unwrap_or_else(|_| ulid::Ulid::new().0 as u64)
-        } else {
-            return Err("Task proxy requires a async task process".to_string());
+    /// Create a child of this container
+    /// A child container has its own scope but will reach out to
+    /// this container (its parent) when an instance of a type does
+    /// not exist locally. The parent in turn falls back to its own parent,
+    /// allowing arbitrarily deep chains (app -> tenant -> request -> job)
+    ///
+    /// Registrations made on the child are never visible to the parent.
+    /// As with proxies, resolvers of the parent are given the child, so they can
+    /// use what was registered in it. Values of `resolver_once` closures resolved
+    /// this way are stored in the child
+    pub fn child(&self) -> Self {
+        let ci = Self {
+            id: ulid::Ulid::new().0 as u64,
+            parent: Some(Arc::new(self.clone())),
+            root: self.proxy_root(),
+            ..Default::default()
         };
+        ci.container
+            .set_default_timeout(self.container.default_timeout());
+
+        ci
+    }
 
-        let mut ci = Self::default();
-        ci.id = id;
-        ci.in_proxy_mode = true;
-        ci.is_task_mode = true;
+    /// Runs the future returned by the closure with a new child of this container
+    ///
+    /// The child is the implicit container for `helpers::*` calls made by the future.
+    /// Every instance and resolver registered in the child is dropped when the future
+    /// completes, panics or is cancelled, even if a clone of the child escaped
+    pub async fn scope<F, Fut>(&self, callback: F) -> Fut::Output
+    where
+        F: FnOnce(ServiceContainer) -> Fut,
+        Fut: Future,
+    {
+        let scope = self.child();
+        let _guard = scope.scope_guard();
 
-        if let Some(mutex) = TASK_SERVICE_CONTAINER.get()
-            && let Ok(mut lock) = mutex.lock()
-        {
-            let counter = AtomicUsize::new(1);
-            lock.insert(id, (counter, ci.container.clone()));
-            drop(lock);
+        current::with_container(scope.clone(), callback(scope)).await
+    }
+
+    /// Returns a guard that disposes the registrations made directly on
+    /// this container when dropped
+    pub(crate) fn scope_guard(&self) -> ScopeGuard {
+        ScopeGuard(self.container.clone())
+    }
+
+    /// Captures the instances and resolvers registered directly on this container
+    ///
+    /// Registrations of the parent, task or global container are not included
+    pub async fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            container_id: self.id,
+            registrations: self.container.snapshot().await,
         }
+    }
 
-        Ok(ci)
+    /// Reverts the registrations of this container to the ones captured by the snapshot
+    ///
+    /// Registrations made since the snapshot was taken are removed. The switch happens
+    /// at once, a concurrent lookup sees either the old or the restored registrations.
+    /// Watchers of every instance that changed or was removed are notified
+    ///
+    /// Returns false and leaves the container untouched when the snapshot
+    /// was taken from another container
+    pub async fn restore(&self, snapshot: &Snapshot) -> bool {
+        self.container.restore(&snapshot.registrations).await
+    }
+
+    /// Returns the parent of this container if it is a child container
+    pub fn parent(&self) -> Option<&ServiceContainer> {
+        self.parent.as_deref()
+    }
+
+    /// Returns a new proxy service container that is tie to the current task
+    ///
+    /// If this method is called outside of a task context, an error will be return
+    pub fn make_task_proxy() -> Result<Self, String> {
+        Self::make_task_proxy_of(None)
+    }
+
+    fn make_task_proxy_of(root: Option<Arc<ServiceContainer>>) -> Result<Self, String> {
+        let scope = TaskScope::current_or_create(Self::id_of_root(&root))
+            .ok_or_else(|| "Task proxy requires a async task process".to_string())?;
+
+        Ok(Self::from_task_scope(scope, root))
+    }
+
+    /// Creates a task proxy holding the task scope
+    fn from_task_scope(scope: Arc<TaskScope>, root: Option<Arc<ServiceContainer>>) -> Self {
+        let mut ci = Self::proxy();
+        ci.id = scope.id;
+        ci.container = scope.container.clone();
+        ci.task_scope = Some(scope);
+        ci.root = root;
+
+        ci
     }
 
     /// Returns the task proxy state of the current container
     pub fn is_task_proxy(&self) -> bool {
-        self.is_task_mode
+        self.task_scope.is_some()
+    }
+
+    /// Describes the task scopes that are still alive
+    ///
+    /// A task scope is shared by the task proxies created within a task. It is
+    /// removed when a future wrapped by `task_scope` ends. The scope of a task
+    /// spawned without `task_scope` is removed with its last task proxy
+    pub fn task_scopes() -> Vec<TaskScopeInfo> {
+        TaskScope::live()
     }
 
     /// Returns the proxy state of the current container
@@ -265,56 +973,226 @@ impl ServiceContainer {
         }
     }
 
-    /// Tries to find the instance of the type wrapped in `Service<T>`
-    pub async fn get<T: 'static>(&self) -> Option<Service<T>> {
-        self.get_type::<Service<T>>().await
+    /// Tries to find the instance of the type wrapped in `Service<T>`
+    pub async fn get<T: 'static>(&self) -> Option<Service<T>> {
+        self.get_type::<Service<T>>().await
+    }
+
+    /// Removes the instance and the resolver of the type from this container
+    /// and reports what was removed. The resolver is never called
+    ///
+    /// The instance is handed back as `Removed::Shared` while a `Snapshot` still holds it
+    pub async fn forget_type<T: Send + Sync + 'static>(&self) -> Forgotten<T> {
+        self.container.forget::<T>().await
+    }
+
+    pub async fn forget_resolver<T: 'static>(&self) -> bool {
+        self.container.remove_resolver::<T>().await
+    }
+
+    /// Same as `forget_type` for the type wrapped in `Service<T>`
+    pub async fn forget<T: Send + Sync + 'static>(&self) -> Forgotten<Service<T>> {
+        self.forget_type().await
+    }
+
+    /// Removes every registration matching the predicate from this container.
+    /// Returns the number of registrations removed
+    ///
+    /// The containers it falls back to are left untouched and resolvers are not called
+    pub async fn forget_where(&self, predicate: impl Fn(&Registration) -> bool) -> usize {
+        self.container
+            .remove_where(|meta| {
+                predicate(&Registration {
+                    type_name: meta.type_name,
+                    kind: meta.kind,
+                    container_id: self.id,
+                    layer: 0,
+                    order: meta.order,
+                })
+            })
+            .await
+    }
+
+    /// Removes every instance and resolver registered with this container.
+    /// Returns the number of registrations removed
+    pub async fn clear(&self) -> usize {
+        self.container.remove_where(|_| true).await
+    }
+
+    /// Removes every resolver registered with this container, keeping the instances.
+    /// Returns the number of resolvers removed
+    pub async fn clear_resolvers(&self) -> usize {
+        self.container
+            .remove_where(|meta| meta.kind != RegistrationKind::Instance)
+            .await
+    }
+
+    /// Tries to find the "raw" instance of the type
+    pub async fn get_type<T: Clone + 'static>(&self) -> Option<T> {
+        self.fetch::<T>().await.ok().flatten()
     }
 
-    pub async fn forget_type<T: 'static>(&self) -> Option<Box<T>> {
-        self.container.forget::<T>(self.make_reference()).await
+    /// Tries to find the "raw" instance of the type.
+    ///
+    /// Unlike `get_type`, the reason for failing is returned. This includes
+    /// a resolver taking longer than its timeout
+    pub async fn try_get_type<T: Clone + 'static>(&self) -> Result<T, ResolveError> {
+        self.fetch::<T>().await?.ok_or(ResolveError::NotFound {
+            type_name: type_name::<T>(),
+        })
     }
 
-    pub async fn forget_resolver<T: 'static>(&self) -> bool {
-        self.container.remove_resolver::<T>().await
+    async fn fetch<T: Clone + 'static>(&self) -> Result<Option<T>, ResolveError> {
+        self.fetch_for(self).await
     }
 
-    pub async fn forget<T: 'static>(&self) -> Option<Box<Service<T>>> {
-        self.forget_type().await
+    /// Looks the type up in this container and the ones it falls back to.
+    /// Resolvers are given the requester, so the resolvers of a parent or root
+    /// can use what was registered in the container the lookup started from
+    async fn fetch_for<T: Clone + 'static>(
+        &self,
+        requester: &ServiceContainer,
+    ) -> Result<Option<T>, ResolveError> {
+        telemetry::lookup(self, type_name::<T>(), async {
+            let watch = Stopwatch::start();
+            let found = self.lookup::<T>(requester).await;
+            let layer = found
+                .as_ref()
+                .ok()
+                .and_then(|found| found.as_ref().map(|(_, layer)| *layer));
+
+            if let Some(layer) = layer {
+                telemetry::record_layer(layer);
+            }
+            self.container.metrics.record_lookup::<T>(watch, layer);
+
+            found.map(|found| found.map(|(value, _)| value))
+        })
+        .await
     }
 
-    /// Tries to find the "raw" instance of the type
-    pub async fn get_type<T: Clone + 'static>(&self) -> Option<T> {
-        let value = self.container.get::<T>(self.make_reference()).await;
-        if value.is_some() {
-            return value;
+    async fn lookup<T: Clone + 'static>(
+        &self,
+        requester: &ServiceContainer,
+    ) -> Result<Option<(T, Layer)>, ResolveError> {
+        if let Some(value) = self
+            .container
+            .try_get::<T>(requester.make_reference())
+            .await?
+        {
+            return Ok(Some((value, Layer::Local)));
+        }
+
+        if let Some(parent) = &self.parent {
+            return Ok(Box::pin(parent.fetch_for(requester))
+                .await?
+                .map(|value| (value, Layer::Parent)));
         }
 
         if !self.is_task_proxy()
             && self.is_proxy()
-            && let Some(sc) = Self::get_task_instance()
+            && let Some(sc) = Self::get_task_instance_of(self.root.clone())
+            && let Some(value) = Box::pin(sc.fetch_for(requester)).await?
         {
-            let result = Box::pin(sc.get_type()).await;
-            if result.is_some() {
-                return result;
-            }
+            return Ok(Some((value, Layer::Task)));
         }
 
         if self.is_proxy() {
-            let value = Box::pin(
-                service_container()
-                    .container
-                    .get::<T>(self.make_reference()),
-            )
-            .await;
+            let root = self.root();
+            let value = Box::pin(root.container.try_get::<T>(requester.make_reference())).await?;
 
             if value.is_some() {
-                return value;
+                return Ok(value.map(|value| (value, Layer::Global)));
             }
 
-            return Box::pin(service_container().get_type()).await;
+            return Ok(Box::pin(root.fetch_for(requester))
+                .await?
+                .map(|value| (value, Layer::Global)));
         }
 
-        None
+        Ok(None)
+    }
+
+    /// Returns every registration in this container and in the containers
+    /// it falls back to, in lookup order.
+    ///
+    /// Within a container, registrations are listed in the order they were made
+    pub async fn registrations(&self) -> Vec<Registration> {
+        let mut registrations = Vec::new();
+        for (layer, (container_id, container)) in self.layers().into_iter().enumerate() {
+            registrations.extend(
+                container
+                    .entries()
+                    .await
+                    .into_iter()
+                    .map(|meta| Registration {
+                        type_name: meta.type_name,
+                        kind: meta.kind,
+                        container_id,
+                        layer,
+                        order: meta.order,
+                    }),
+            );
+        }
+
+        registrations
+    }
+
+    /// Returns a human readable listing of the registrations.
+    /// Useful when debugging
+    pub async fn dump(&self) -> String {
+        let mut output = format!("{self}");
+        for registration in self.registrations().await {
+            _ = write!(output, "\n  {registration}");
+        }
+
+        output
+    }
+
+    /// Checks if an instance of the type is stored, can be resolved or is
+    /// absent from this container and the containers it falls back to
+    pub async fn contains<T: 'static>(&self) -> Presence {
+        for (_, container) in self.layers() {
+            let presence = container.presence::<T>().await;
+            if presence != Presence::Absent {
+                return presence;
+            }
+        }
+
+        Presence::Absent
+    }
+
+    /// Returns a snapshot of the resolution metrics recorded by this container
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> crate::metrics::ResolutionStats {
+        self.container.metrics.snapshot(self.id)
+    }
+
+    /// Returns the ID and store of this container followed by the ones
+    /// of the containers it falls back to
+    pub(crate) fn layers(&self) -> Vec<(u64, Container)> {
+        let mut layers = vec![(self.id, self.container.clone())];
+
+        if let Some(parent) = &self.parent {
+            layers.extend(parent.layers());
+        } else if self.is_proxy() {
+            if !self.is_task_proxy()
+                && let Some(sc) = Self::get_task_instance_of(self.root.clone())
+            {
+                layers.push((sc.id, sc.container.clone()));
+            }
+
+            let root = self.root();
+            layers.push((root.id, root.container.clone()));
+        }
+
+        layers
+    }
+
+    /// Returns the store of this container
+    #[cfg(feature = "testing")]
+    pub(crate) fn store(&self) -> Container {
+        self.container.clone()
     }
 
     pub(crate) async fn instance<T: Clone + 'static>(&self) -> Option<T> {
@@ -323,11 +1201,7 @@ impl ServiceContainer {
 
     /// Stores the instance
     pub async fn set_type<T: Clone + Send + Sync + 'static>(&self, value: T) -> &Self {
-        self.resolver(move |_| {
-            let c = value.clone();
-            Box::pin(async move { c })
-        })
-        .await;
+        self.container.set_value(value).await;
         self
     }
 
@@ -336,30 +1210,128 @@ impl ServiceContainer {
         self
     }
 
-    pub(crate) fn get_task_instance() -> Option<ServiceContainer> {
-        let id = if let Some(id) = tokio::task::try_id() {
-            id.to_string()
-                .parse::<u64>()
-                .unwrap_or_else(|_| ulid::Ulid::new().0 as u64)
-        } else {
-            return None;
-        };
+    /// Stores the instance from a synchronous context.
+    /// Returns false when the container is locked
+    pub(crate) fn try_remember<T: Clone + Send + Sync + 'static>(&self, value: T) -> bool {
+        self.container.try_set(value)
+    }
 
-        let mutex = TASK_SERVICE_CONTAINER.get_or_init(std::sync::Mutex::default);
-        if let Ok(mut lock) = mutex.lock()
-            && let Some((counter, c)) = lock.get_mut(&id)
-        {
-            counter.fetch_add(1, std::sync::atomic::Ordering::Acquire);
-            let mut instance = Self::proxy();
-            instance.id = id;
-            instance.container = c.clone();
-            instance.is_task_mode = true;
-            return Some(instance);
+    pub(crate) fn get_task_instance_of(
+        root: Option<Arc<ServiceContainer>>,
+    ) -> Option<ServiceContainer> {
+        TaskScope::current(Self::id_of_root(&root)).map(|scope| Self::from_task_scope(scope, root))
+    }
+
+    /// Stores the instance as it is. The type does not need to implement `Clone`
+    ///
+    /// Use `with_type` to access the instance without cloning it
+    pub async fn set_instance<T: Send + Sync + 'static>(&self, value: T) -> &Self {
+        self.container.set(value).await;
+        self
+    }
+
+    /// Runs the closure against the stored instance of the type without cloning it.
+    /// The container and the containers it falls back to are searched, resolvers are not called
+    ///
+    /// No lock is held while the closure runs, so it can register values in the
+    /// container. The closure keeps seeing the instance it was given when that
+    /// type is replaced in the meantime
+    pub async fn with_type<T: 'static, R>(&self, callback: impl FnOnce(&T) -> R) -> Option<R> {
+        self.with_type_async(async |value: &T| callback(value))
+            .await
+    }
+
+    /// Same as `with_type` but the closure is async
+    pub async fn with_type_async<T: 'static, R>(
+        &self,
+        callback: impl AsyncFnOnce(&T) -> R,
+    ) -> Option<R> {
+        let type_id = TypeId::of::<T>();
+        for (_, container) in self.layers() {
+            if let Some(value) = container.instance(type_id).await
+                && let Some(value) = value.downcast_ref::<T>()
+            {
+                return Some(callback(value).await);
+            }
         }
 
         None
     }
 
+    /// Modifies the instance of the type stored in this container in place and
+    /// returns the result of the closure
+    ///
+    /// Like `set_type`, only this container is written to. Instances of the parent,
+    /// task or global container are left untouched. Lookups made while the closure
+    /// runs wait for it to complete, so concurrent updates are never lost.
+    /// None is returned when no instance is stored, resolvers are not called
+    pub async fn update<T: Clone + Send + Sync + 'static, R>(
+        &self,
+        callback: impl FnOnce(&mut T) -> R,
+    ) -> Option<R> {
+        self.container.update(callback).await.ok()
+    }
+
+    /// Returns a receiver of the latest instance of the type
+    ///
+    /// The receiver watches the container that currently provides the type, or
+    /// this container when none does. It is notified each time the type is set,
+    /// updated or forgotten in that container. Dropping every receiver
+    /// stops the notifications
+    ///
+    /// ```rust
+    /// # use busybody::*;
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let container = ServiceContainer::proxy();
+    /// container.set_type(1_u8).await;
+    ///
+    /// let mut watch = container.watch::<u8>().await;
+    /// container.set_type(2_u8).await;
+    ///
+    /// watch.changed().await.unwrap();
+    /// assert_eq!(watch.get(), Some(2));
+    /// # }
+    /// ```
+    pub async fn watch<T: Clone + Send + Sync + 'static>(&self) -> Watch<T> {
+        let mut store = self.container.clone();
+        for (_, container) in self.layers() {
+            if container.presence::<T>().await != Presence::Absent {
+                store = container;
+                break;
+            }
+        }
+
+        let (channel, created) = store.channel::<T>();
+        if created {
+            channel.init(store.get::<T>(self.make_reference()).await);
+        }
+
+        Watch::new(channel)
+    }
+
+    /// Stores the value wrapped in a `State<T>`, allowing it to be shared and modified
+    pub async fn set_state<T: Send + Sync + 'static>(&self, value: T) -> &Self {
+        self.set_type(State::new(value)).await
+    }
+
+    /// Stores a `Pool<T>` creating its objects with the factory
+    pub async fn set_pool<T: Send + Sync + 'static, F>(
+        &self,
+        options: PoolOptions<T>,
+        factory: impl Fn() -> F + Send + Sync + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.set_type(Pool::new(options, factory)).await
+    }
+
+    /// Runs the closure against the instance of the type wrapped in `Service<T>`
+    pub async fn with_service<T: 'static, R>(&self, callback: impl FnOnce(&T) -> R) -> Option<R> {
+        self.get::<T>().await.map(|service| callback(&service))
+    }
+
     /// Stores the instance as `Service<T>`
     /// You need to use "get" in order to retrieve the instance
     pub async fn set<T: Send + Sync + 'static>(&self, ext: T) -> &Self {
@@ -377,13 +1349,51 @@ impl ServiceContainer {
     where
         F: Future<Output = T> + Send + 'static,
     {
-        self.container.resolver(callback).await;
+        self.container
+            .resolver(RegistrationKind::Resolver, callback)
+            .await;
+        self
+    }
+
+    /// Registers a closure that will be call each time
+    /// an instance of the specified type is requested
+    /// This closure will override existing closure for this type
+    ///
+    /// If the closure does not complete within the timeout, the lookup fails
+    /// with `ResolveError::Timeout`
+    pub async fn resolver_with_timeout<T: Send + Sync + 'static, F>(
+        &self,
+        timeout: Duration,
+        callback: impl FnMut(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.container
+            .resolver_with_timeout(RegistrationKind::Resolver, Some(timeout), callback)
+            .await;
         self
     }
 
+    /// Sets the timeout applied to resolvers registered in this container
+    /// without their own timeout. `None` removes the timeout
+    ///
+    /// Child containers created afterwards inherit this value
+    pub fn set_default_resolve_timeout(&self, timeout: Option<Duration>) -> &Self {
+        self.container.set_default_timeout(timeout);
+        self
+    }
+
+    /// Returns the timeout applied to resolvers registered without their own timeout
+    pub fn default_resolve_timeout(&self) -> Option<Duration> {
+        self.container.default_timeout()
+    }
+
     pub async fn resolvable<T: Resolver + Clone + Send + Sync + 'static>(&self) -> &Self {
         self.container
-            .resolver(|c| async move { T::resolve(&c).await })
+            .resolver(RegistrationKind::Resolver, |c| async move {
+                T::resolve(&c).await
+            })
             .await;
         self
     }
@@ -424,13 +1434,44 @@ impl ServiceContainer {
         // callback: impl Fn(ServiceContainer) -> BoxFuture<'static, T> + Send + Sync + Copy + 'static,
         callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
     ) -> &Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.register_once(None, callback).await
+    }
+
+    /// Registers a closure that will be call the first time
+    /// an instance of the specified type is requested
+    /// This closure will override existing closure for this type
+    ///
+    /// If the closure does not complete within the timeout, the lookup fails
+    /// with `ResolveError::Timeout` and nothing is stored. The next lookup
+    /// will call the closure again
+    pub async fn resolver_once_with_timeout<T: Clone + Send + Sync + 'static, F>(
+        &self,
+        timeout: Duration,
+        callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.register_once(Some(timeout), callback).await
+    }
+
+    async fn register_once<T: Clone + Send + Sync + 'static, F>(
+        &self,
+        timeout: Option<Duration>,
+        callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> &Self
     where
         F: Future<Output = T> + Send + 'static,
     {
         self.container
-            .resolver(move |container| {
+            .resolver_with_timeout(RegistrationKind::ResolverOnce, timeout, move |container| {
                 let f = (callback)(container.clone());
                 Box::pin(async move {
+                    // The value is only stored once the factory completes.
+                    // If the future is dropped (ie: timed out) the resolver stays in place
                     let value = f.await;
                     container.set_type(value.clone()).await;
                     value
@@ -462,6 +1503,65 @@ impl ServiceContainer {
         self
     }
 
+    /// Registers a closure whose value is cached for the duration
+    /// This closure will override existing closure for this type
+    ///
+    /// Once the value expires, the next request calls the closure again.
+    /// Requests made while the closure runs wait for its value instead of calling
+    /// it too. Each container the closure is registered with caches its own value
+    pub async fn resolver_ttl<T: Clone + Send + Sync + 'static, F>(
+        &self,
+        ttl: Duration,
+        callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.register_ttl(TtlCache::new(ttl, false), callback).await
+    }
+
+    /// Same as `resolver_ttl` but an expired value is still returned while
+    /// the closure produces the next one in the background.
+    /// Only the first request waits for the closure
+    pub async fn resolver_ttl_stale<T: Clone + Send + Sync + 'static, F>(
+        &self,
+        ttl: Duration,
+        callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.register_ttl(TtlCache::new(ttl, true), callback).await
+    }
+
+    async fn register_ttl<T: Clone + Send + Sync + 'static, F>(
+        &self,
+        cache: TtlCache<T>,
+        callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let factory: Arc<TtlFactory<T>> = Arc::new(move |c| Box::pin(callback(c)));
+        self.container.ttl_resolver(cache, factory).await;
+        self
+    }
+
+    /// Drops the value cached by the `resolver_ttl` closure of the type.
+    /// The next request calls the closure again
+    ///
+    /// The container and the containers it falls back to are searched, only the
+    /// first cached resolver found is invalidated. Returns false when none exists
+    pub async fn invalidate<T: 'static>(&self) -> bool {
+        for (_, container) in self.layers() {
+            if container.invalidate::<T>().await {
+                return true;
+            }
+        }
+
+        false
+    }
+
     /// Takes an async function or closure and execute it
     /// Require arguments are resolve either by a resolver or sourced from the service container
     ///
@@ -471,8 +1571,11 @@ impl ServiceContainer {
         F: Handler<Args>,
         Args: Clone + Resolver + 'static,
     {
-        let args = self.resolve_all().await;
-        handler.call(args).await
+        telemetry::call("resolve_and_call", self, type_name::<Args>(), async move {
+            let args = self.resolve_all().await;
+            handler.call(args).await
+        })
+        .await
     }
 
     /// Given a tuple of types, this method will try to resolve them
@@ -482,27 +1585,53 @@ impl ServiceContainer {
     where
         Args: Clone + Resolver + 'static,
     {
-        if let Some(a) = self.get_type::<Args>().await {
-            return a;
-        }
+        telemetry::call("resolve_all", self, type_name::<Args>(), async {
+            if let Some(a) = self.get_type::<Args>().await {
+                return a;
+            }
+
+            Args::resolve(self).await
+        })
+        .await
+    }
 
-        Args::resolve(self).await
+    /// Takes an async function or closure and execute it
+    /// Require arguments are resolve either by a resolver or sourced from the service container
+    ///
+    /// Unlike `resolve_and_call`, an error naming the type that could not be resolved
+    /// is returned instead of panicking. This includes a resolver taking longer than its timeout
+    pub async fn try_resolve_and_call<F, Args>(
+        &self,
+        mut handler: F,
+    ) -> Result<F::Output, ResolveError>
+    where
+        F: Handler<Args>,
+        Args: Clone + Resolver + Send + 'static,
+    {
+        telemetry::call("resolve_and_call", self, type_name::<Args>(), async move {
+            let args = self.try_resolve_all().await?;
+            Ok(handler.call(args).await)
+        })
+        .await
     }
-}
 
-impl Drop for ServiceContainer {
-    fn drop(&mut self) {
-        if self.is_task_proxy()
-            && let Some(mutex) = TASK_SERVICE_CONTAINER.get()
-            && let Ok(mut lock) = mutex.lock()
-        {
-            if let Some((counter, sc)) = lock.remove(&self.id)
-                && counter.fetch_sub(1, std::sync::atomic::Ordering::Acquire) > 0
-            {
-                lock.insert(self.id, (counter, sc));
+    /// Given a tuple of types, this method will try to resolve them
+    /// by using a resolver or cloning an existing instance in the container
+    ///
+    /// Unlike `resolve_all`, an error naming the type that could not be resolved
+    /// is returned instead of panicking
+    pub async fn try_resolve_all<Args>(&self) -> Result<Args, ResolveError>
+    where
+        Args: Clone + Resolver + Send + 'static,
+    {
+        telemetry::call("resolve_all", self, type_name::<Args>(), async {
+            if let Some(a) = self.fetch::<Args>().await? {
+                return Ok(a);
             }
-            drop(lock);
-        }
+
+            Args::try_resolve(self).await
+        })
+        .await
     }
 }
 
@@ -519,7 +1648,7 @@ impl Default for ServiceContainerBuilder {
 impl ServiceContainerBuilder {
     pub fn new() -> Self {
         Self {
-            service_container: SERVICE_CONTAINER.get_or_init(ServiceContainer::new).clone(),
+            service_container: global_container(),
         }
     }
 
@@ -533,6 +1662,44 @@ impl ServiceContainerBuilder {
         }
     }
 
+    /// Create a new root container that does not fall back to the global container
+    ///
+    /// Proxies, task proxies and children created from it fall back to it instead,
+    /// allowing independent applications or tests to live in the same process
+    pub fn isolated() -> Self {
+        Self {
+            service_container: ServiceContainer::isolated(),
+        }
+    }
+
+    /// Sets the timeout applied to resolvers registered without their own timeout
+    ///
+    /// A resolver taking longer fails the lookup with `ResolveError::Timeout`
+    pub fn default_resolve_timeout(self, timeout: Duration) -> Self {
+        self.service_container
+            .set_default_resolve_timeout(Some(timeout));
+        self
+    }
+
+    /// Registers a closure that will be call each time
+    /// an instance of the specified type is requested
+    /// This closure will override existing closure for this type
+    ///
+    /// If the closure does not complete within the timeout, the lookup fails
+    pub async fn resolver_with_timeout<T: Clone + Send + Sync + 'static, F>(
+        self,
+        timeout: Duration,
+        callback: impl FnMut(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.service_container
+            .resolver_with_timeout(timeout, callback)
+            .await;
+        self
+    }
+
     /// Registers an instance of a type
     pub async fn register<T: Clone + Send + Sync + 'static>(self, ext: T) -> Self {
         self.service_container.set_type(ext).await;
@@ -623,12 +1790,63 @@ impl ServiceContainerBuilder {
     ///       of the global service container
     pub async fn soft_resolver_once<T: Clone + Send + Sync + 'static, F>(
         self,
-        callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
+        callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.service_container.soft_resolver_once(callback).await;
+        self
+    }
+
+    /// Registers a closure whose value is cached for the duration
+    /// See `ServiceContainer::resolver_ttl`
+    pub async fn resolver_ttl<T: Clone + Send + Sync + 'static, F>(
+        self,
+        ttl: Duration,
+        callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.service_container.resolver_ttl(ttl, callback).await;
+        self
+    }
+
+    /// Same as `resolver_ttl` but serves the expired value while refreshing it
+    /// See `ServiceContainer::resolver_ttl_stale`
+    pub async fn resolver_ttl_stale<T: Clone + Send + Sync + 'static, F>(
+        self,
+        ttl: Duration,
+        callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.service_container
+            .resolver_ttl_stale(ttl, callback)
+            .await;
+        self
+    }
+
+    /// T is wrapped in a `State`
+    /// This means to get T back you need to specify `State<T>`
+    pub async fn state<T: Send + Sync + 'static>(self, value: T) -> Self {
+        self.service_container.set_state(value).await;
+        self
+    }
+
+    /// Registers a `Pool<T>` creating its objects with the factory
+    /// See `ServiceContainer::set_pool`
+    pub async fn pool<T: Send + Sync + 'static, F>(
+        self,
+        options: PoolOptions<T>,
+        factory: impl Fn() -> F + Send + Sync + 'static,
     ) -> Self
     where
         F: Future<Output = T> + Send + 'static,
     {
-        self.service_container.soft_resolver_once(callback).await;
+        self.service_container.set_pool(options, factory).await;
         self
     }
 
@@ -647,10 +1865,13 @@ impl ServiceContainerBuilder {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+
     use async_trait::async_trait;
 
-    use crate::helpers::service_container;
+    use crate::{Live, WatchClosed, helpers::service_container};
 
     use super::*;
 
@@ -724,10 +1945,11 @@ mod test {
         assert_eq!(*container.get::<bool>().await.unwrap(), true);
     }
 
-    #[tokio::test]
+    #[cfg(feature = "tokio")]
+    #[busybody::test]
     async fn test_proxy_service() {
         service_container().set_type(true).await;
-        let container = ServiceContainer::proxy();
+        let container = service_container().new_proxy();
 
         let is_true: Option<bool> = container.get_type().await;
         let an_i32: Option<i32> = container.get_type().await;
@@ -740,6 +1962,19 @@ mod test {
         assert_eq!(rate_per_hour, Some(30000));
     }
 
+    #[cfg(feature = "tokio")]
+    async fn fixtures(container: &ServiceContainer) {
+        container.set_type(7_u8).await;
+    }
+
+    #[cfg(feature = "tokio")]
+    #[busybody::test(setup = fixtures, flavor = "multi_thread")]
+    async fn test_isolated_test_setup() {
+        assert!(service_container().is_isolated());
+        assert_eq!(service_container().get_type::<u8>().await, Some(7));
+        assert_eq!(ServiceContainer::proxy().get_type::<u8>().await, None);
+    }
+
     #[tokio::test]
     async fn test_injecting() {
         let container = ServiceContainer::proxy();
@@ -824,9 +2059,11 @@ mod test {
         assert_eq!(container.get_type::<usize>().await, Some(300_usize));
 
         let value = container.forget_type::<usize>().await;
-        assert_eq!(value.is_some(), true);
+        assert_eq!(value.value(), Some(&300));
+        assert!(!value.removed_resolver());
 
         assert_eq!(container.get_type::<usize>().await, None);
+        assert!(container.forget_type::<usize>().await.is_nothing());
     }
 
     #[tokio::test]
@@ -838,8 +2075,8 @@ mod test {
         container.set(300_usize).await;
         assert_eq!(*container.get::<usize>().await.unwrap(), 300_usize);
 
-        let value = container.forget::<usize>().await;
-        assert_eq!(value.is_some(), true);
+        let value = container.forget::<usize>().await.into_value();
+        assert_eq!(value.as_deref().map(|service| **service), Some(300));
 
         assert_eq!(container.get::<usize>().await.is_none(), true);
     }
@@ -929,6 +2166,796 @@ mod test {
         assert_eq!(counter.0, 100);
     }
 
+    #[tokio::test]
+    async fn test_child_container_chain() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct AppName(&'static str);
+        #[derive(Debug, Clone, PartialEq)]
+        struct TenantId(u32);
+        #[derive(Debug, Clone, PartialEq)]
+        struct RequestId(u32);
+
+        let app = ServiceContainer::proxy();
+        app.set_type(AppName("busybody")).await;
+
+        let tenant = app.child();
+        tenant.set_type(TenantId(7)).await;
+
+        let request = tenant.child();
+        request.set_type(RequestId(42)).await;
+
+        let job = request.child();
+
+        assert_eq!(job.get_type::<AppName>().await, Some(AppName("busybody")));
+        assert_eq!(job.get_type::<TenantId>().await, Some(TenantId(7)));
+        assert_eq!(job.get_type::<RequestId>().await, Some(RequestId(42)));
+        assert_eq!(job.parent().map(|p| p.id()), Some(request.id()));
+
+        assert_eq!(tenant.get_type::<RequestId>().await, None);
+        assert_eq!(app.get_type::<TenantId>().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_child_container_sets_current_layer_only() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Locale(&'static str);
+
+        let parent = ServiceContainer::proxy();
+        parent.set_type(Locale("en")).await;
+
+        let child = parent.child();
+        child.set_type(Locale("fr")).await;
+
+        assert_eq!(child.get_type::<Locale>().await, Some(Locale("fr")));
+        assert_eq!(parent.get_type::<Locale>().await, Some(Locale("en")));
+    }
+
+    #[tokio::test]
+    async fn test_parent_resolvers_see_the_requesting_child() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Header(&'static str);
+        #[derive(Debug, Clone, PartialEq)]
+        struct User(Option<Header>);
+
+        let app = ServiceContainer::proxy();
+        app.resolver(|c| async move { User(c.get_type::<Header>().await) })
+            .await;
+
+        let request = app.child();
+        request.set_type(Header("token")).await;
+
+        assert_eq!(
+            request.child().get_type::<User>().await,
+            Some(User(Some(Header("token"))))
+        );
+        assert_eq!(app.get_type::<User>().await, Some(User(None)));
+    }
+
+    #[tokio::test]
+    async fn test_scope_disposes_registrations() {
+        #[derive(Clone)]
+        struct Resource(#[allow(dead_code)] Arc<DropFlag>);
+
+        struct DropFlag(Arc<AtomicBool>);
+
+        impl Drop for DropFlag {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let resource = |flag: &Arc<AtomicBool>| Resource(Arc::new(DropFlag(flag.clone())));
+        let parent = ServiceContainer::proxy();
+        parent.set_type(1_u8).await;
+
+        // completion
+        let dropped = Arc::new(AtomicBool::new(false));
+        let escaped = parent
+            .scope(|scope| async {
+                service_container().set_type(resource(&dropped)).await;
+                assert_eq!(service_container().get_type::<u8>().await, Some(1));
+                assert!(scope.get_type::<Resource>().await.is_some());
+                scope
+            })
+            .await;
+        assert!(dropped.load(Ordering::SeqCst));
+        assert!(escaped.get_type::<Resource>().await.is_none());
+        assert!(parent.get_type::<Resource>().await.is_none());
+
+        // panic
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = dropped.clone();
+        let task_parent = parent.clone();
+        let result = tokio::spawn(async move {
+            task_parent
+                .scope(|scope| async move {
+                    scope.set_type(resource(&flag)).await;
+                    panic!("scope failed");
+                })
+                .await
+        })
+        .await;
+        assert!(result.is_err());
+        assert!(dropped.load(Ordering::SeqCst));
+
+        // cancellation
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = dropped.clone();
+        let result = tokio::time::timeout(
+            Duration::from_millis(10),
+            parent.scope(|scope| async move {
+                scope.set_type(resource(&flag)).await;
+                futures::future::pending::<()>().await;
+            }),
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_isolated_root() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct AppName(&'static str);
+        #[derive(Debug, Clone, PartialEq)]
+        struct GlobalOnly;
+        #[derive(Debug, Clone, PartialEq)]
+        struct TaskValue(u8);
+
+        service_container().set_type(GlobalOnly).await;
+
+        let first = ServiceContainerBuilder::isolated()
+            .register(AppName("first"))
+            .await
+            .build();
+        let second = ServiceContainerBuilder::isolated()
+            .register(AppName("second"))
+            .await
+            .build();
+
+        assert!(first.is_isolated());
+        assert_ne!(first.id(), second.id());
+        assert_eq!(first.get_type::<GlobalOnly>().await, None);
+        assert_eq!(first.new_proxy().get_type::<GlobalOnly>().await, None);
+        assert_eq!(
+            first.new_proxy().get_type::<AppName>().await,
+            Some(AppName("first"))
+        );
+        assert_eq!(
+            second.child().new_proxy().get_type::<AppName>().await,
+            Some(AppName("second"))
+        );
+        assert_eq!(
+            ServiceContainer::proxy().get_type::<GlobalOnly>().await,
+            Some(GlobalOnly)
+        );
+
+        let name = current::with_container(first.new_proxy(), async {
+            crate::helpers::make_proxy().get_type::<AppName>().await
+        })
+        .await;
+        assert_eq!(name, Some(AppName("first")));
+
+        tokio::spawn(crate::task_scope(async move {
+            let task_proxy = first.new_task_proxy().unwrap();
+            task_proxy.set_type(TaskValue(5)).await;
+
+            assert_eq!(
+                first.new_proxy().get_type::<TaskValue>().await,
+                Some(TaskValue(5))
+            );
+            assert_eq!(second.new_proxy().get_type::<TaskValue>().await, None);
+            assert_eq!(
+                ServiceContainer::proxy().get_type::<TaskValue>().await,
+                None
+            );
+        }))
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_and_restore() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Config(&'static str);
+        #[derive(Debug, Clone, PartialEq)]
+        struct Added;
+
+        let container = ServiceContainer::proxy();
+        container.set_type(Config("stable")).await;
+        container.resolver(|_| async { 1_u8 }).await;
+        container.remember(Service::new(5_u16)).await;
+
+        let snapshot = container.snapshot().await;
+        assert_eq!(snapshot.container_id(), container.id());
+
+        // The snapshot still holds the forgotten values
+        assert!(matches!(
+            container.forget_type::<Config>().await,
+            Forgotten::Instance(Removed::Shared(value)) if *value == Config("stable")
+        ));
+        assert!(matches!(
+            container.forget::<u16>().await,
+            Forgotten::Instance(Removed::Shared(value)) if **value == 5
+        ));
+
+        container.set_type(Config("experimental")).await;
+        container.set_type(Added).await;
+        container.forget_resolver::<u8>().await;
+        assert!(matches!(
+            container.forget_type::<Added>().await,
+            Forgotten::Instance(Removed::Owned(Added))
+        ));
+        container.set_type(Added).await;
+
+        container.restore(&snapshot).await;
+        assert_eq!(container.get_type().await, Some(Config("stable")));
+        assert_eq!(container.get_type::<Added>().await, None);
+        assert_eq!(container.get_type::<u8>().await, Some(1));
+        assert_eq!(container.get::<u16>().await.as_deref(), Some(&5));
+
+        // A snapshot can be restored more than once
+        container.set_type(Added).await;
+        assert!(container.restore(&snapshot).await);
+        assert_eq!(container.get_type::<Added>().await, None);
+
+        // Snapshots of other containers are rejected
+        let other = ServiceContainer::proxy();
+        other.set_type(Added).await;
+        assert!(!other.restore(&snapshot).await);
+        assert_eq!(other.get_type::<Added>().await, Some(Added));
+        assert_eq!(other.get_type::<Config>().await, None);
+
+        // Containers sharing an ID are told apart by their store
+        let global = ServiceContainer::new();
+        global.set_type(Added).await;
+        let unrelated = ServiceContainer::default().snapshot().await;
+        assert_eq!(unrelated.container_id(), global.id());
+        assert!(!global.restore(&unrelated).await);
+        assert_eq!(global.get_type::<Added>().await, Some(Added));
+        assert!(global.restore(&global.snapshot().await).await);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_task_scope_lifetime() {
+        assert!(ServiceContainer::make_task_proxy().is_err());
+
+        let (id, escaped) = tokio::spawn(crate::task_scope(async {
+            let first = ServiceContainer::make_task_proxy().unwrap();
+            let second = ServiceContainer::make_task_proxy().unwrap();
+            assert_eq!(first.id(), second.id());
+
+            drop(first.clone());
+            drop(second);
+            first.set_type(3_u8).await;
+            assert_eq!(ServiceContainer::proxy().get_type::<u8>().await, Some(3));
+
+            let info = ServiceContainer::task_scopes()
+                .into_iter()
+                .find(|info| info.container_id() == first.id())
+                .unwrap();
+            assert_eq!(info.root_id(), GLOBAL_INSTANCE_ID);
+            assert_eq!(info.handles(), 1);
+            assert!(info.task().starts_with("scoped-"));
+
+            (first.id(), first)
+        }))
+        .await
+        .unwrap();
+
+        // The scope ends with its task, even though a task proxy escaped it
+        assert!(
+            ServiceContainer::task_scopes()
+                .iter()
+                .all(|info| info.container_id() != id)
+        );
+        assert_eq!(escaped.get_type::<u8>().await, None);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_task_scope_outlives_task_proxies() {
+        let task = tokio::spawn(crate::task_scope(async {
+            crate::helpers::make_task_proxy()
+                .unwrap()
+                .set_type(5_i64)
+                .await;
+            tokio::task::yield_now().await;
+            let proxy = crate::helpers::make_task_proxy().unwrap();
+            assert_eq!(proxy.get_type::<i64>().await, Some(5));
+            let id = proxy.id();
+            drop(proxy);
+
+            let info = ServiceContainer::task_scopes()
+                .into_iter()
+                .find(|info| info.container_id() == id)
+                .unwrap();
+            assert_eq!(info.handles(), 0);
+            id
+        }));
+        let id = task.await.unwrap();
+        assert!(
+            ServiceContainer::task_scopes()
+                .iter()
+                .all(|info| info.container_id() != id)
+        );
+
+        // Without `task_scope` the scope is dropped with its last task proxy
+        let (id, task) = tokio::spawn(async {
+            let proxy = crate::helpers::make_task_proxy().unwrap();
+            proxy.set_type(6_i64).await;
+            let id = proxy.id();
+            assert!(
+                ServiceContainer::task_scopes()
+                    .iter()
+                    .any(|info| info.container_id() == id && info.handles() == 1)
+            );
+            drop(proxy);
+
+            (id, tokio::task::id().to_string())
+        })
+        .await
+        .unwrap();
+        assert!(
+            ServiceContainer::task_scopes()
+                .iter()
+                .all(|info| info.container_id() != id && info.task() != task)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_with_type() {
+        #[derive(Debug, PartialEq)]
+        struct LookupTable(Vec<u32>);
+
+        let parent = ServiceContainer::proxy();
+        parent.set_instance(LookupTable(vec![1, 2, 3])).await;
+        parent.set(String::from("service")).await;
+        let child = parent.child();
+
+        assert_eq!(
+            child.with_type(|table: &LookupTable| table.0.len()).await,
+            Some(3)
+        );
+        assert_eq!(
+            child
+                .with_type_async(async |table: &LookupTable| {
+                    tokio::task::yield_now().await;
+                    table.0.iter().sum::<u32>()
+                })
+                .await,
+            Some(6)
+        );
+        assert_eq!(
+            child.with_service(|value: &String| value.len()).await,
+            Some(7)
+        );
+        assert_eq!(child.with_type(|value: &u128| *value).await, None);
+
+        parent.set_type(String::from("x")).await;
+        assert_eq!(child.with_type(|value: &String| value.len()).await, Some(1));
+        child.resolver(|_| async { 7_u128 }).await;
+        assert_eq!(child.with_type(|value: &u128| *value).await, None);
+
+        parent.set_instance(9_u8).await;
+        assert_eq!(child.get_type::<u8>().await, Some(9));
+
+        // The closure can register values, including the type it was given
+        let length = parent
+            .with_type_async(async |table: &LookupTable| {
+                parent.set_type(table.0.len()).await;
+                parent.set_instance(LookupTable(vec![])).await;
+                table.0.len()
+            })
+            .await;
+        assert_eq!(length, Some(3));
+        assert_eq!(parent.get_type::<usize>().await, Some(3));
+        assert_eq!(
+            child.with_type(|table: &LookupTable| table.0.len()).await,
+            Some(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Counter(u32);
+
+        let parent = ServiceContainer::proxy();
+        parent.set_type(Counter(0)).await;
+        parent.set_instance(String::from("a")).await;
+        let child = parent.child();
+
+        assert_eq!(parent.update(|c: &mut Counter| c.0 += 1).await, Some(()));
+        assert_eq!(
+            parent
+                .update(|value: &mut String| {
+                    value.push('b');
+                    value.len()
+                })
+                .await,
+            Some(2)
+        );
+        assert_eq!(parent.get_type().await, Some(Counter(1)));
+        assert_eq!(parent.get_type().await, Some(String::from("ab")));
+        assert_eq!(parent.update(|value: &mut u128| *value += 1).await, None);
+
+        // Writes go to the current layer only
+        assert_eq!(child.update(|c: &mut Counter| c.0 += 1).await, None);
+        assert_eq!(child.get_type().await, Some(Counter(1)));
+
+        let snapshot = parent.snapshot().await;
+        let tasks = (0..10)
+            .map(|_| {
+                let parent = parent.clone();
+                tokio::spawn(async move { parent.update(|c: &mut Counter| c.0 += 1).await })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(parent.get_type().await, Some(Counter(11)));
+
+        parent.restore(&snapshot).await;
+        assert_eq!(parent.get_type().await, Some(Counter(1)));
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Config(u8);
+
+        let parent = ServiceContainer::proxy();
+        parent.set_type(Config(1)).await;
+        let child = parent.child();
+
+        let mut watch = child.watch::<Config>().await;
+        assert_eq!(watch.get(), Some(Config(1)));
+        assert!(!watch.has_changed());
+
+        let waiting = tokio::spawn({
+            let mut watch = watch.clone();
+            let child = child.clone();
+            async move {
+                watch.changed().await.unwrap();
+                // The value is stored by the time watchers are notified
+                (watch.get(), child.get_type::<Config>().await)
+            }
+        });
+        tokio::task::yield_now().await;
+        parent.set_type(Config(2)).await;
+        assert_eq!(waiting.await.unwrap(), (Some(Config(2)), Some(Config(2))));
+
+        parent.update(|config: &mut Config| config.0 = 3).await;
+        watch.changed().await.unwrap();
+        assert_eq!(watch.get(), Some(Config(3)));
+
+        parent.forget_type::<Config>().await;
+        watch.changed().await.unwrap();
+        assert_eq!(watch.get_and_update(), None);
+
+        parent.set_instance(Config(4)).await;
+        assert!(watch.has_changed());
+        assert_eq!(watch.get(), Some(Config(4)));
+
+        let snapshot = parent.snapshot().await;
+        parent.set_instance(Config(5)).await;
+        assert_eq!(watch.get_and_update(), Some(Config(5)));
+        parent.restore(&snapshot).await;
+        assert!(watch.has_changed());
+        assert_eq!(watch.get_and_update(), Some(Config(4)));
+
+        // Restoring the same values again changes nothing
+        parent.restore(&snapshot).await;
+        assert!(!watch.has_changed());
+
+        // Removing or replacing a value registered with `set_type`
+        let mut level = parent.set_type(1_i8).await.watch::<i8>().await;
+        assert!(parent.forget_resolver::<i8>().await);
+        assert!(level.has_changed());
+        assert_eq!(level.get_and_update(), None);
+        parent.set_type(2_i8).await;
+        assert_eq!(level.get_and_update(), Some(2));
+        parent.resolver(|_| async { 3_i8 }).await;
+        assert!(level.has_changed());
+        assert_eq!(level.get_and_update(), None);
+
+        let scope = ServiceContainer::proxy();
+        let mut closed = scope.watch::<Config>().await;
+        assert_eq!(closed.get(), None);
+        drop(scope.scope_guard());
+        assert_eq!(closed.changed().await, Err(WatchClosed));
+    }
+
+    #[tokio::test]
+    async fn test_live() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Flags(bool);
+
+        let container = ServiceContainer::proxy();
+        container.set_type(Flags(false)).await;
+        container.resolvable::<Live<Flags>>().await;
+
+        let flags = container
+            .child()
+            .resolve_and_call(async |flags: Live<Flags>| flags)
+            .await;
+        assert_eq!(flags.get(), Some(Flags(false)));
+
+        container.set_type(Flags(true)).await;
+        assert_eq!(flags.get(), Some(Flags(true)));
+    }
+
+    #[tokio::test]
+    async fn test_resolver_ttl() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Token(usize);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let parent = ServiceContainer::proxy();
+        parent
+            .resolver_ttl(Duration::from_millis(50), {
+                let calls = calls.clone();
+                move |_| {
+                    let calls = calls.clone();
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        Token(calls.fetch_add(1, Ordering::SeqCst) + 1)
+                    }
+                }
+            })
+            .await;
+        let child = parent.child();
+
+        let lookups = (0..5)
+            .map(|_| {
+                let child = child.clone();
+                tokio::spawn(async move { child.get_type::<Token>().await })
+            })
+            .collect::<Vec<_>>();
+        for lookup in lookups {
+            assert_eq!(lookup.await.unwrap(), Some(Token(1)));
+        }
+        assert_eq!(parent.get_type().await, Some(Token(1)));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(child.get_type().await, Some(Token(2)));
+
+        assert!(child.invalidate::<Token>().await);
+        assert_eq!(parent.get_type().await, Some(Token(3)));
+        assert!(!child.invalidate::<u8>().await);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(parent.contains::<Token>().await, Presence::Resolvable);
+    }
+
+    #[tokio::test]
+    async fn test_resolver_ttl_stale() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let container = ServiceContainerBuilder::new_proxy()
+            .resolver_ttl_stale(Duration::from_millis(20), {
+                let calls = calls.clone();
+                move |_| {
+                    let calls = calls.clone();
+                    async move { calls.fetch_add(1, Ordering::SeqCst) + 1 }
+                }
+            })
+            .await
+            .build();
+
+        assert_eq!(container.get_type::<usize>().await, Some(1));
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        assert_eq!(container.get_type::<usize>().await, Some(1));
+        while calls.load(Ordering::SeqCst) < 2 {
+            tokio::task::yield_now().await;
+        }
+        while container.get_type::<usize>().await != Some(2) {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_state() {
+        #[derive(Debug, Default)]
+        struct Hits(u32);
+
+        let container = ServiceContainerBuilder::new_proxy()
+            .state(Hits::default())
+            .await
+            .build();
+
+        for _ in 0..3 {
+            container
+                .resolve_and_call(async |hits: State<Hits>| hits.write().await.0 += 1)
+                .await;
+        }
+
+        let hits = container.get_type::<State<Hits>>().await.unwrap();
+        assert_eq!(hits.read().await.0, 3);
+    }
+
+    #[tokio::test]
+    async fn test_registrations() {
+        #[derive(Debug, Clone)]
+        struct Config;
+
+        let parent = ServiceContainer::proxy();
+        parent.resolver(|_| async { 200_u32 }).await;
+
+        let container = parent.child();
+        container.set_type(Config).await;
+        container.resolver_once(|_| async { 300_u64 }).await;
+        container.soft_resolver(|_| async { 40_i8 }).await;
+
+        let registrations = container.registrations().await;
+        let local = registrations
+            .iter()
+            .filter(|r| r.layer() == 0)
+            .map(|r| (r.type_name(), r.kind()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            local,
+            vec![
+                (type_name::<Config>(), RegistrationKind::Instance),
+                ("u64", RegistrationKind::ResolverOnce),
+                ("i8", RegistrationKind::SoftResolver),
+            ]
+        );
+        assert!(registrations.iter().any(|r| r.layer() == 1
+            && r.container_id() == parent.id()
+            && r.type_name() == "u32"
+            && r.kind() == RegistrationKind::Resolver));
+
+        let dump = container.dump().await;
+        assert!(dump.contains(type_name::<Config>()));
+        assert!(dump.contains("soft resolver"));
+    }
+
+    #[tokio::test]
+    async fn test_contains() {
+        #[derive(Debug, Clone)]
+        struct Stored;
+        #[derive(Debug, Clone)]
+        struct Resolvable;
+        struct Absent;
+
+        let parent = ServiceContainer::proxy();
+        parent.set_type(Stored).await;
+
+        let container = parent.child();
+        container.resolver(|_| async { Resolvable }).await;
+
+        assert_eq!(container.contains::<Stored>().await, Presence::Stored);
+        assert_eq!(
+            container.contains::<Resolvable>().await,
+            Presence::Resolvable
+        );
+        assert_eq!(container.contains::<Absent>().await, Presence::Absent);
+        assert_eq!(parent.contains::<Resolvable>().await, Presence::Absent);
+    }
+
+    #[tokio::test]
+    async fn test_resolver_timeout() {
+        #[derive(Debug, Clone)]
+        struct RemoteConnection;
+
+        let container = ServiceContainer::proxy();
+        container
+            .resolver_with_timeout(Duration::from_millis(10), |_| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                RemoteConnection
+            })
+            .await;
+
+        let error = container
+            .try_get_type::<RemoteConnection>()
+            .await
+            .unwrap_err();
+        assert!(error.is_timeout());
+        assert_eq!(error.type_name(), type_name::<RemoteConnection>());
+        assert!(container.get_type::<RemoteConnection>().await.is_none());
+
+        let result = container
+            .try_resolve_and_call(|_: RemoteConnection| async {})
+            .await;
+        assert_eq!(
+            result.unwrap_err().type_name(),
+            type_name::<RemoteConnection>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_default_resolve_timeout() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct SlowService;
+
+        let container = ServiceContainerBuilder::new_proxy()
+            .default_resolve_timeout(Duration::from_millis(10))
+            .resolver(|_| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                SlowService
+            })
+            .await
+            .build();
+
+        assert_eq!(
+            container.try_get_type::<SlowService>().await,
+            Err(ResolveError::Timeout {
+                type_name: type_name::<SlowService>(),
+                timeout: Duration::from_millis(10)
+            })
+        );
+        assert_eq!(
+            container.child().default_resolve_timeout(),
+            Some(Duration::from_millis(10))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolver_once_timeout_does_not_register() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Connection(usize);
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let container = ServiceContainer::proxy();
+        let counter = attempts.clone();
+        container
+            .resolver_once_with_timeout(Duration::from_millis(10), move |_| {
+                let attempt = counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if attempt == 0 {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                    Connection(attempt)
+                }
+            })
+            .await;
+
+        assert!(
+            container
+                .try_get_type::<Connection>()
+                .await
+                .unwrap_err()
+                .is_timeout()
+        );
+        assert_eq!(
+            container.contains::<Connection>().await,
+            Presence::Resolvable
+        );
+
+        assert_eq!(
+            container.get_type::<Connection>().await,
+            Some(Connection(1))
+        );
+        assert_eq!(container.contains::<Connection>().await, Presence::Stored);
+        assert_eq!(
+            container.get_type::<Connection>().await,
+            Some(Connection(1))
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_try_resolve_all_not_found() {
+        struct Unknown;
+
+        let container = ServiceContainer::proxy();
+        container.set_type(1_u8).await;
+
+        assert_eq!(
+            container
+                .try_resolve_all::<(u8, Service<Unknown>)>()
+                .await
+                .map(|(n, _)| n),
+            Err(ResolveError::NotFound {
+                type_name: type_name::<Service<Unknown>>()
+            })
+        );
+    }
+
     #[tokio::test]
     async fn test_forgetting_resolver() {
         let container = ServiceContainer::proxy();
@@ -941,4 +2968,58 @@ mod test {
         let number2 = container.get_type::<i32>().await;
         assert_eq!(number2.is_none(), true);
     }
+
+    #[tokio::test]
+    async fn test_forgetting_does_not_call_resolvers() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let container = ServiceContainer::proxy();
+        container
+            .resolver({
+                let calls = calls.clone();
+                move |_| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async { 1_u32 }
+                }
+            })
+            .await;
+        container.set_instance(2_u32).await;
+
+        let forgotten = container.forget_type::<u32>().await;
+        assert!(matches!(forgotten, Forgotten::Both(Removed::Owned(2))));
+
+        container.resolver(|_| async { 3_u64 }).await;
+        assert!(matches!(
+            container.forget_type::<u64>().await,
+            Forgotten::Resolver
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_clear() {
+        #[derive(Debug, Clone)]
+        struct Endpoint;
+
+        let parent = ServiceContainer::proxy();
+        parent.set_type(1_u8).await;
+        let container = parent.child();
+        container.set_type(2_u8).await;
+        container.set_instance(Endpoint).await;
+        container.resolver(|_| async { 3_u16 }).await;
+        container.resolver_once(|_| async { 4_u32 }).await;
+
+        assert_eq!(container.clear_resolvers().await, 2);
+        assert_eq!(container.get_type::<u16>().await, None);
+        assert_eq!(container.get_type::<u8>().await, Some(2));
+
+        let removed = container
+            .forget_where(|registration| registration.type_name().ends_with("Endpoint"))
+            .await;
+        assert_eq!(removed, 1);
+        assert!(container.with_type(|_: &Endpoint| ()).await.is_none());
+
+        assert_eq!(container.clear().await, 1);
+        assert_eq!(container.get_type::<u8>().await, Some(1));
+        assert_eq!(container.clear().await, 0);
+    }
 }
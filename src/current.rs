@@ -0,0 +1,123 @@
+//! Tracks the container used by the `helpers::*` functions in the current task
+
+use std::cell::RefCell;
+
+use crate::{ServiceContainer, runtime};
+
+thread_local! {
+    static CURRENT_CONTAINER: RefCell<Vec<ServiceContainer>> = const { RefCell::new(Vec::new()) };
+}
+
+#[cfg(feature = "testing")]
+thread_local! {
+    static TEST_CONTAINERS: RefCell<Vec<ServiceContainer>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Returns the container installed for the current task, if any
+pub(crate) fn current_container() -> Option<ServiceContainer> {
+    runtime::current(&CURRENT_CONTAINER).or_else(test_container)
+}
+
+/// Returns a new container for a request or a call of an integration
+///
+/// It is a child of the parent when one was configured, otherwise of the implicit
+/// container. A proxy of the global container is returned when neither exists
+#[cfg(any(
+    feature = "axum",
+    feature = "actix",
+    feature = "tower",
+    feature = "tonic"
+))]
+pub(crate) fn request_scope(parent: Option<&ServiceContainer>) -> ServiceContainer {
+    match parent.cloned().or_else(current_container) {
+        Some(parent) => parent.child(),
+        None => ServiceContainer::proxy(),
+    }
+}
+
+/// Returns the innermost test container installed on this thread
+#[cfg(feature = "testing")]
+fn test_container() -> Option<ServiceContainer> {
+    runtime::current(&TEST_CONTAINERS)
+}
+
+#[cfg(not(feature = "testing"))]
+fn test_container() -> Option<ServiceContainer> {
+    None
+}
+
+/// Installs the container as the implicit container of this thread
+#[cfg(feature = "testing")]
+pub(crate) fn push_test_container(container: ServiceContainer) {
+    TEST_CONTAINERS.with_borrow_mut(|containers| containers.push(container));
+}
+
+/// Uninstalls the test container with the ID
+#[cfg(feature = "testing")]
+pub(crate) fn remove_test_container(id: u64) {
+    TEST_CONTAINERS.with_borrow_mut(|containers| {
+        if let Some(index) = containers
+            .iter()
+            .rposition(|container| container.id() == id)
+        {
+            containers.remove(index);
+        }
+    });
+}
+
+/// Runs the future with the container as the implicit container
+///
+/// While the future runs, `helpers::service_container()` returns this container,
+/// so every `helpers::*` call made by the future uses it. Calls can be nested, the
+/// innermost container wins. Tasks spawned by the future do not inherit it.
+///
+/// ```rust
+/// # use busybody::*;
+/// # #[tokio::main]
+/// # async fn main() {
+/// let tenant = ServiceContainerBuilder::new_proxy().register(7_u32).await.build();
+///
+/// let id = with_container(tenant, async { helpers::get_type::<u32>().await }).await;
+/// assert_eq!(id, Some(7));
+/// # }
+/// ```
+pub async fn with_container<F: Future>(container: ServiceContainer, fut: F) -> F::Output {
+    runtime::Scoped::new(&CURRENT_CONTAINER, container, fut).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::helpers;
+
+    #[tokio::test]
+    async fn test_with_container() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Tenant(&'static str);
+
+        let first = ServiceContainer::proxy();
+        first.set_type(Tenant("first")).await;
+        let second = ServiceContainer::proxy();
+        second.set_type(Tenant("second")).await;
+
+        assert!(current_container().is_none());
+
+        with_container(first.clone(), async {
+            assert_eq!(helpers::service_container().id(), first.id());
+            assert_eq!(helpers::get_type().await, Some(Tenant("first")));
+
+            with_container(second.clone(), async {
+                assert_eq!(helpers::get_type().await, Some(Tenant("second")));
+                helpers::set_type(1_u8).await;
+            })
+            .await;
+
+            assert_eq!(helpers::get_type().await, Some(Tenant("first")));
+            assert_eq!(helpers::get_type::<u8>().await, None);
+        })
+        .await;
+
+        assert!(current_container().is_none());
+        assert_eq!(second.get_type::<u8>().await, Some(1));
+    }
+}
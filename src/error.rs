@@ -0,0 +1,40 @@
+use std::{fmt::Display, time::Duration};
+
+/// Errors returned by the fallible resolving methods
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    /// No instance or resolver exist for the type
+    NotFound { type_name: &'static str },
+    /// The resolver for the type did not complete within the allowed time
+    Timeout {
+        type_name: &'static str,
+        timeout: Duration,
+    },
+}
+
+impl ResolveError {
+    /// The full type name of the type that could not be resolved
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::NotFound { type_name } | Self::Timeout { type_name, .. } => type_name,
+        }
+    }
+
+    /// Returns true if the error was caused by a resolver timing out
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Timeout { .. })
+    }
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound { type_name } => write!(f, "could not resolve: {type_name}"),
+            Self::Timeout { type_name, timeout } => {
+                write!(f, "resolving {type_name} timed out after {timeout:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
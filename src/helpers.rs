@@ -1,7 +1,10 @@
 #![allow(dead_code)]
 
+use std::time::Duration;
+
 use crate::{
-    Resolver, ServiceContainer, ServiceContainerBuilder, handlers::Handler, service::Service,
+    Forgotten, Registration, ResolveError, Resolver, ServiceContainer, ServiceContainerBuilder,
+    current::current_container, handlers::Handler, service::Service, watch::Watch,
 };
 
 /// Takes an async function or closure and execute it
@@ -17,10 +20,22 @@ where
     service_container().resolve_and_call(handler).await
 }
 
+/// Takes an async function or closure and execute it
+/// Require arguments are resolve either by a resolver or sourced from the service container
+///
+/// An error naming the type that could not be resolved is returned instead of panicking.
+pub async fn try_resolve_and_call<F, Args>(handler: F) -> Result<F::Output, ResolveError>
+where
+    F: Handler<Args>,
+    Args: Clone + Resolver + Send + 'static,
+{
+    service_container().try_resolve_and_call(handler).await
+}
+
 /// Given a tuple of types, this function will try to resolve them
 /// by using a resolver or cloning an existing instance in the container
 ///
-/// The global service container is used.
+/// The current container is used, see `service_container`
 pub async fn resolve_all<Args>() -> Args
 where
     Args: Resolver,
@@ -42,7 +57,7 @@ where
 /// Require arguments are resolve either by a resolver or sourced from the service container
 ///
 /// This function will use an existing if one exist.
-/// This  function will use the provided service container before fallback back to the global one
+/// This function will use the provided service container before falling back to its parents and root
 pub async fn resolve_and_call_with<F, Args>(ci: &ServiceContainer, handler: F) -> F::Output
 where
     F: Handler<Args>,
@@ -54,14 +69,30 @@ where
 /// Given a type, this function will try to find an instance of the type
 /// wrapped in a `Service<T>` that is currently registered in the service
 /// container.
-/// The global service container is used as the resolver.
+/// The current container is used as the resolver, see `service_container`
 pub async fn service<T: Send + Sync + 'static>() -> Service<T> {
     service_container().get_type().await.unwrap()
 }
 
-/// Returns the global service container instance
+/// Returns the service container used by the helper functions
+///
+/// This is the global service container unless a container was installed
+/// for the current task by `with_container`, `ServiceContainer::scope`
+/// or a request scope layer, or a `TestContainer` is alive on this thread
 pub fn service_container() -> ServiceContainer {
-    ServiceContainerBuilder::new().build()
+    current_container().unwrap_or_else(|| ServiceContainerBuilder::new().build())
+}
+
+/// Runs the future returned by the closure with a new child of the current container
+///
+/// Everything registered in the child is dropped once the future completes.
+/// See `ServiceContainer::scope`
+pub async fn scope<F, Fut>(callback: F) -> Fut::Output
+where
+    F: FnOnce(ServiceContainer) -> Fut,
+    Fut: Future,
+{
+    service_container().scope(callback).await
 }
 
 /// Returns an instance of the service builder
@@ -70,32 +101,76 @@ pub fn make_builder() -> ServiceContainerBuilder {
 }
 
 /// Tries to get an instance of the type if one exist in the container
-/// This function uses the global container
+/// This function uses the current container, see `service_container`
 pub async fn get_type<T: Clone + 'static>() -> Option<T> {
     service_container().get_type().await
 }
 
+/// Tries to get an instance of the type if one exist in the container
+/// The reason for failing, including a resolver timing out, is returned
+/// This function uses the current container, see `service_container`
+pub async fn try_get_type<T: Clone + 'static>() -> Result<T, ResolveError> {
+    service_container().try_get_type().await
+}
+
 /// Tries to get an instance of the type's service if one exist in the container
-/// This function uses the global container
+/// This function uses the current container, see `service_container`
 pub async fn get_service<T: 'static>() -> Option<Service<T>> {
     service_container().get::<T>().await
 }
 
-/// Removes the registered instance of the type specified and returns it
-/// This function uses the global container
-pub async fn forget_type<T: 'static>() -> Option<Box<T>> {
+/// Runs the closure against the instance of the type without cloning it
+/// See `ServiceContainer::with_type`
+pub async fn with_type<T: 'static, R>(callback: impl FnOnce(&T) -> R) -> Option<R> {
+    service_container().with_type(callback).await
+}
+
+/// Modifies the instance of the type stored in the current container in place
+/// See `ServiceContainer::update`
+pub async fn update<T: Clone + Send + Sync + 'static, R>(
+    callback: impl FnOnce(&mut T) -> R,
+) -> Option<R> {
+    service_container().update(callback).await
+}
+
+/// Returns a receiver of the latest instance of the type
+/// See `ServiceContainer::watch`
+pub async fn watch<T: Clone + Send + Sync + 'static>() -> Watch<T> {
+    service_container().watch().await
+}
+
+/// Removes the registered instance and resolver of the type specified
+/// See `ServiceContainer::forget_type`
+pub async fn forget_type<T: Send + Sync + 'static>() -> Forgotten<T> {
     service_container().forget_type().await
 }
 
-/// Removes the registered service instance of the type specified and returns it
-/// This function uses the global container
-pub async fn forget<T: 'static>() -> Option<Box<Service<T>>> {
+/// Removes the registered service instance and resolver of the type specified
+/// See `ServiceContainer::forget`
+pub async fn forget<T: Send + Sync + 'static>() -> Forgotten<Service<T>> {
     service_container().forget().await
 }
 
+/// Removes every registration matching the predicate
+/// See `ServiceContainer::forget_where`
+pub async fn forget_where(predicate: impl Fn(&Registration) -> bool) -> usize {
+    service_container().forget_where(predicate).await
+}
+
+/// Removes every instance and resolver
+/// See `ServiceContainer::clear`
+pub async fn clear() -> usize {
+    service_container().clear().await
+}
+
+/// Removes every resolver, keeping the instances
+/// See `ServiceContainer::clear_resolvers`
+pub async fn clear_resolvers() -> usize {
+    service_container().clear_resolvers().await
+}
+
 /// Register a service instance
-/// The instance is registered with the global service container
-/// This function uses the global container
+/// The instance is registered with the current container, see `service_container`
 pub async fn register_service<T: Send + Sync + 'static>(ext: T) -> ServiceContainer {
     let container = service_container();
     container.set(ext).await;
@@ -104,8 +179,7 @@ pub async fn register_service<T: Send + Sync + 'static>(ext: T) -> ServiceContai
 }
 
 /// Register a type instance
-/// The instance is registered with the global service container
-/// This function uses the global container
+/// The instance is registered with the current container, see `service_container`
 pub async fn register_type<T: Clone + Send + Sync + 'static>(ext: T) -> ServiceContainer {
     let container = service_container();
     container.set_type(ext).await;
@@ -113,10 +187,27 @@ pub async fn register_type<T: Clone + Send + Sync + 'static>(ext: T) -> ServiceC
     container
 }
 
+/// Register an instance that does not need to implement `Clone`
+/// See `ServiceContainer::set_instance`
+pub async fn set_instance<T: Send + Sync + 'static>(ext: T) -> ServiceContainer {
+    let container = service_container();
+    container.set_instance(ext).await;
+
+    container
+}
+
+/// Register a value wrapped in a `State<T>`
+/// See `ServiceContainer::set_state`
+pub async fn set_state<T: Send + Sync + 'static>(value: T) -> ServiceContainer {
+    let container = service_container();
+    container.set_state(value).await;
+
+    container
+}
+
 /// Register a type instance
 /// Same as `register_type`
-/// The instance is registered with the global service container
-/// This function uses the global container
+/// The instance is registered with the current container, see `service_container`
 pub async fn set_type<T: Clone + Send + Sync + 'static>(ext: T) -> ServiceContainer {
     let container = service_container();
     container.set_type(ext).await;
@@ -127,7 +218,7 @@ pub async fn set_type<T: Clone + Send + Sync + 'static>(ext: T) -> ServiceContai
 /// Registers a closure that will be call each time
 /// an instance of the specified type is requested
 /// This closure will override existing closure for this type
-/// This function uses the global container
+/// This function uses the current container, see `service_container`
 ///
 pub async fn resolver<T: Clone + Send + Sync + 'static, F>(
     callback: impl FnMut(ServiceContainer) -> F + Send + Sync + 'static,
@@ -142,7 +233,7 @@ where
 
 /// Registers a type as resolvable
 /// Existing resolver for this type will be replaced
-/// This function uses the global container
+/// This function uses the current container, see `service_container`
 ///
 pub async fn resolvable<T: Resolver + Clone + Send + Sync + 'static>() -> ServiceContainer {
     let c = service_container();
@@ -166,11 +257,11 @@ pub async fn soft_resolvable<T: Resolver + Clone + Send + Sync + 'static>() -> S
 /// an instance of the specified type is requested
 /// This closure will override existing closure for this type
 ///
-/// The returned instance will be store in the global service container
+/// The returned instance will be store in the current container
 /// and subsequent request for this type will resolve to that copy.
 ///
 /// Note: The service container passed to your callback is the instance
-///       of the global service container
+///       of the current container
 pub async fn resolver_once<T: Clone + Send + Sync + 'static, F>(
     callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
 ) -> ServiceContainer
@@ -182,13 +273,33 @@ where
     c
 }
 
+/// Registers a closure whose value is cached for the duration
+/// See `ServiceContainer::resolver_ttl`
+pub async fn resolver_ttl<T: Clone + Send + Sync + 'static, F>(
+    ttl: Duration,
+    callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
+) -> ServiceContainer
+where
+    F: Future<Output = T> + Send + 'static,
+{
+    let c = service_container();
+    c.resolver_ttl(ttl, callback).await;
+    c
+}
+
+/// Drops the value cached by the `resolver_ttl` closure of the type
+/// See `ServiceContainer::invalidate`
+pub async fn invalidate<T: 'static>() -> bool {
+    service_container().invalidate::<T>().await
+}
+
 /// Registers a closure that will be call each time
 /// an instance of the specified type is requested
 /// If a closure already registered for this type, this one will be ignore
 ///
 ///
 /// Note: The service container passed to your callback is the instance
-///       of the global service container
+///       of the current container
 pub async fn soft_resolver<T: Clone + Send + Sync + 'static, F>(
     callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
 ) -> ServiceContainer
@@ -204,11 +315,11 @@ where
 /// an instance of the specified type is requested
 /// If a closure already registered for this type, this one will be ignore
 ///
-/// The returned instance will be store in the global service container
+/// The returned instance will be store in the current container
 /// and subsequent request for this type will resolve to that copy.
 ///
 /// Note: The service container passed to your callback is the instance
-///       of the global service container
+///       of the current container
 pub async fn soft_resolver_once<T: Clone + Send + Sync + 'static, F>(
     callback: impl Fn(ServiceContainer) -> F + Send + Sync + 'static,
 ) -> ServiceContainer
@@ -221,20 +332,22 @@ where
 }
 
 /// Returns a new proxy service container
+///
+/// The proxy falls back to the root of the current container
 pub fn make_proxy() -> ServiceContainer {
-    ServiceContainer::proxy()
+    service_container().new_proxy()
 }
 
 /// Returns a new proxy service container that is tie to the current task
 ///
 /// If this function is called outside of a task context, an error will be return
 pub fn make_task_proxy() -> Result<ServiceContainer, String> {
-    ServiceContainer::make_task_proxy()
+    service_container().new_task_proxy()
 }
 
 /// Returns a new proxy service container that is tie to the current task
 ///
 /// If this function is called outside of a task context, a normal proxy instance will be returned.
 pub fn make_task_proxy_or_fallback() -> ServiceContainer {
-    ServiceContainer::make_task_proxy().unwrap_or_else(|_| make_proxy())
+    make_task_proxy().unwrap_or_else(|_| make_proxy())
 }
@@ -241,16 +241,93 @@
 //!
 
 mod container;
+mod current;
+mod error;
 mod handlers;
+mod local;
+mod metrics;
+mod pool;
+mod registration;
 mod resolver;
+mod runtime;
 mod service;
+mod snapshot;
+mod state;
+mod task;
+mod telemetry;
+mod ttl;
+mod watch;
 
 pub mod helpers;
 
+#[cfg(feature = "actix")]
+pub mod actix;
+
+#[cfg(feature = "axum")]
+pub mod axum;
+
+#[cfg(feature = "config")]
+pub mod config;
+
+#[cfg(feature = "tonic")]
+pub mod tonic;
+
+#[cfg(feature = "tower")]
+pub mod tower;
+
+#[cfg(feature = "smol")]
+pub mod smol;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
 pub use container::ServiceContainer;
 pub use container::ServiceContainerBuilder;
+pub use current::with_container;
+pub use error::ResolveError;
 pub use handlers::*;
+pub use local::{LocalResolver, LocalService, LocalServiceContainer};
+#[cfg(feature = "metrics")]
+pub use metrics::{Histogram, ResolutionStats, TypeStats};
+pub use pool::{Pool, PoolGuard, PoolOptions};
+pub use registration::{Forgotten, Presence, Registration, RegistrationKind, Removed};
 pub use resolver::Resolver;
 pub use service::Service;
+pub use snapshot::Snapshot;
+pub use state::{State, StateReadGuard, StateWriteGuard};
+pub use task::{TaskScopeInfo, task_scope};
+pub use watch::{Live, Watch, WatchClosed};
 
 pub use async_trait::async_trait;
+/// Marks an async function as a test that runs with its own isolated root container
+///
+/// The container is the implicit container of the test body, so every `helpers::*`
+/// call resolves against it instead of the global container. Tests can therefore
+/// run in parallel without seeing each other's registrations.
+///
+/// `setup` names an async function receiving `&ServiceContainer` that registers
+/// fixtures before the body runs. Every other argument is passed on to `#[tokio::test]`
+///
+/// ```rust
+/// use busybody::*;
+///
+/// async fn fixtures(container: &ServiceContainer) {
+///     container.set_type(8080_u16).await;
+/// }
+///
+/// #[busybody::test(setup = fixtures, flavor = "multi_thread")]
+/// async fn reads_the_port() {
+///     assert_eq!(helpers::get_type::<u16>().await, Some(8080));
+/// }
+/// ```
+#[cfg(feature = "tokio")]
+pub use busybody_macros::test;
+
+// Allows the code generated by the macros to refer to this crate from within it
+extern crate self as busybody;
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "tokio")]
+    pub use tokio;
+}
@@ -0,0 +1,522 @@
+//! A service container for values that cannot leave their thread
+//!
+//! `LocalServiceContainer` stores `Rc` based caches, FFI handles and other `!Send`
+//! values. It is meant for single threaded workloads such as a tokio `LocalSet`.
+//! Types it does not know about are looked up in its parent and then in the
+//! regular `ServiceContainer` it falls back to, if any.
+//!
+//! The registering and resolving methods have the same names and signatures as
+//! the ones of `ServiceContainer`, minus the `Send` and `Sync` bounds, so code can
+//! switch between the two containers. The differences are:
+//!
+//! - There are no `helpers::*` functions. A local container is never the implicit
+//!   container, use its methods instead of `resolve_all_with` and `resolve_and_call_with`
+//! - Resolvers cannot be given a timeout or a time to live. `set_instance`, `with_type`,
+//!   `update`, `watch`, state, pools, snapshots and introspection are only provided
+//!   by `ServiceContainer`
+//! - There are no task proxies, a local container never leaves its thread anyway
+//! - `forget_type` always hands back `Removed::Owned` since nothing else holds the instance
+
+use std::{
+    any::{Any, TypeId, type_name},
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use futures::future::LocalBoxFuture;
+
+use crate::{Forgotten, Handler, Removed, ResolveError, ServiceContainer};
+
+pub type LocalService<T> = Rc<T>;
+
+type LocalResolverFn =
+    Box<dyn FnMut(LocalServiceContainer) -> LocalBoxFuture<'static, Box<dyn Any>>>;
+
+/// Resolves an instance of a type from a `LocalServiceContainer`
+///
+/// Same as `Resolver` but the type and its dependencies do not need to be `Send`
+#[async_trait::async_trait(?Send)]
+pub trait LocalResolver {
+    async fn resolve(container: &LocalServiceContainer) -> Self;
+
+    /// Same as `resolve` but returns an error instead of panicking
+    /// when a required type cannot be resolved
+    async fn try_resolve(container: &LocalServiceContainer) -> Result<Self, ResolveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self::resolve(container).await)
+    }
+}
+
+#[derive(Clone)]
+pub struct LocalServiceContainer {
+    services: Rc<RefCell<HashMap<TypeId, Box<dyn Any>>>>,
+    resolvers: Rc<RefCell<HashMap<TypeId, Rc<RefCell<LocalResolverFn>>>>>,
+    parent: Option<Rc<LocalServiceContainer>>,
+    fallback: Option<ServiceContainer>,
+    id: u64,
+}
+
+impl Debug for LocalServiceContainer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self, f)
+    }
+}
+
+impl Display for LocalServiceContainer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "local service container:: id: {}, child: {}, fallback: {:?}",
+            self.id,
+            self.parent.is_some(),
+            self.fallback.as_ref().map(ServiceContainer::id)
+        )
+    }
+}
+
+impl Default for LocalServiceContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalServiceContainer {
+    /// Create a container that only resolves what is registered with it
+    pub fn new() -> Self {
+        Self {
+            services: Default::default(),
+            resolvers: Default::default(),
+            parent: None,
+            fallback: None,
+            id: ulid::Ulid::new().0 as u64,
+        }
+    }
+
+    /// Create a container that falls back to the service container
+    /// when an instance of a type does not exist locally
+    pub fn with_fallback(container: ServiceContainer) -> Self {
+        Self {
+            fallback: Some(container),
+            ..Self::new()
+        }
+    }
+
+    /// Create a container in proxy mode.
+    /// See `ServiceContainer::proxy`
+    pub fn proxy() -> Self {
+        Self::with_fallback(ServiceContainer::proxy())
+    }
+
+    /// Create a child of this container
+    /// A child container has its own scope but will reach out to
+    /// this container (its parent) when an instance of a type does
+    /// not exist locally
+    pub fn child(&self) -> Self {
+        Self {
+            parent: Some(Rc::new(self.clone())),
+            ..Self::new()
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the parent of this container if it is a child container
+    pub fn parent(&self) -> Option<&LocalServiceContainer> {
+        self.parent.as_deref()
+    }
+
+    /// Returns the service container this container falls back to
+    pub fn fallback(&self) -> Option<&ServiceContainer> {
+        self.fallback.as_ref()
+    }
+
+    /// Stores the instance
+    pub async fn set_type<T: Clone + 'static>(&self, value: T) -> &Self {
+        self.store(value);
+        self
+    }
+
+    /// Stores the instance as `LocalService<T>`
+    /// You need to use "get" in order to retrieve the instance
+    pub async fn set<T: 'static>(&self, ext: T) -> &Self {
+        self.set_type(LocalService::new(ext)).await
+    }
+
+    fn store<T: 'static>(&self, value: T) {
+        self.services
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), Box::new(value));
+    }
+
+    fn has_resolver<T: 'static>(&self) -> bool {
+        self.resolvers.borrow().contains_key(&TypeId::of::<T>())
+    }
+
+    /// Tries to find the "raw" instance of the type
+    pub async fn get_type<T: Clone + 'static>(&self) -> Option<T> {
+        if let Some(value) = self.local::<T>().await {
+            return Some(value);
+        }
+
+        if let Some(parent) = &self.parent {
+            return Box::pin(parent.get_type()).await;
+        }
+
+        match &self.fallback {
+            Some(fallback) => fallback.get_type().await,
+            None => None,
+        }
+    }
+
+    /// Tries to find the "raw" instance of the type.
+    ///
+    /// Unlike `get_type`, an error naming the type is returned
+    pub async fn try_get_type<T: Clone + 'static>(&self) -> Result<T, ResolveError> {
+        self.get_type().await.ok_or(ResolveError::NotFound {
+            type_name: type_name::<T>(),
+        })
+    }
+
+    /// Tries to find the instance of the type wrapped in `LocalService<T>`
+    pub async fn get<T: 'static>(&self) -> Option<LocalService<T>> {
+        self.get_type::<LocalService<T>>().await
+    }
+
+    async fn local<T: Clone + 'static>(&self) -> Option<T> {
+        if let Some(value) = self.services.borrow().get(&TypeId::of::<T>()) {
+            return value.downcast_ref::<T>().cloned();
+        }
+
+        let resolver = self.resolvers.borrow().get(&TypeId::of::<T>()).cloned()?;
+        // The callback is not borrowed while its future runs, allowing it to use this container
+        let future = (resolver.borrow_mut())(self.clone());
+
+        future.await.downcast::<T>().ok().map(|value| *value)
+    }
+
+    /// Removes the instance and the resolver of the type and reports what was removed.
+    /// The resolver is never called
+    pub async fn forget_type<T: 'static>(&self) -> Forgotten<T> {
+        let instance = self
+            .services
+            .borrow_mut()
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| Removed::Owned(*value));
+        let resolver = self.resolvers.borrow_mut().remove(&TypeId::of::<T>());
+
+        Forgotten::new(instance, resolver.is_some())
+    }
+
+    pub async fn forget_resolver<T: 'static>(&self) -> bool {
+        self.resolvers
+            .borrow_mut()
+            .remove(&TypeId::of::<T>())
+            .is_some()
+    }
+
+    pub async fn forget<T: 'static>(&self) -> Forgotten<LocalService<T>> {
+        self.forget_type().await
+    }
+
+    /// Registers a closure that will be call each time
+    /// an instance of the specified type is requested
+    /// This closure will override existing closure for this type
+    ///
+    pub async fn resolver<T: 'static, F>(
+        &self,
+        mut callback: impl FnMut(LocalServiceContainer) -> F + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + 'static,
+    {
+        let callback: LocalResolverFn = Box::new(move |container| {
+            let f = callback(container);
+            Box::pin(async move { Box::new(f.await) as Box<dyn Any> })
+        });
+        self.resolvers
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), Rc::new(RefCell::new(callback)));
+        self
+    }
+
+    /// Registers a closure that will be call the first time
+    /// an instance of the specified type is requested
+    /// This closure will override existing closure for this type
+    ///
+    pub async fn resolver_once<T: Clone + 'static, F>(
+        &self,
+        callback: impl Fn(LocalServiceContainer) -> F + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + 'static,
+    {
+        self.resolver(move |container| {
+            let f = callback(container.clone());
+            async move {
+                let value = f.await;
+                container.store(value.clone());
+                value
+            }
+        })
+        .await
+    }
+
+    /// Registers a closure that will be call each time
+    /// an instance of the specified type is requested
+    /// This closure will be ignored if the type already has a registered resolver
+    ///
+    pub async fn soft_resolver<T: Clone + 'static, F>(
+        &self,
+        callback: impl Fn(LocalServiceContainer) -> F + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + 'static,
+    {
+        if !self.has_resolver::<T>() {
+            self.resolver(callback).await;
+        }
+
+        self
+    }
+
+    /// Registers a closure that will be call the first time
+    /// an instance of the specified type is requested
+    /// This closure will be ignored if the type already has a registered resolver
+    ///
+    pub async fn soft_resolver_once<T: Clone + 'static, F>(
+        &self,
+        callback: impl Fn(LocalServiceContainer) -> F + 'static,
+    ) -> &Self
+    where
+        F: Future<Output = T> + 'static,
+    {
+        if !self.has_resolver::<T>() {
+            self.resolver_once(callback).await;
+        }
+
+        self
+    }
+
+    /// Registers type T as resolvable
+    ///
+    /// This call will override existing resolver for this type
+    pub async fn resolvable<T: LocalResolver + 'static>(&self) -> &Self {
+        self.resolver(|c| async move { T::resolve(&c).await }).await
+    }
+
+    pub async fn resolvable_once<T: LocalResolver + Clone + 'static>(&self) -> &Self {
+        self.resolver_once(|c| async move { T::resolve(&c).await })
+            .await
+    }
+
+    pub async fn soft_resolvable<T: LocalResolver + Clone + 'static>(&self) -> &Self {
+        self.soft_resolver(|c| async move { T::resolve(&c).await })
+            .await
+    }
+
+    /// Given a tuple of types, this method will try to resolve them
+    /// by using a resolver or cloning an existing instance in the container
+    ///
+    pub async fn resolve_all<Args: LocalResolver>(&self) -> Args {
+        Args::resolve(self).await
+    }
+
+    /// Takes an async function or closure and execute it
+    /// Require arguments are resolve either by a resolver or sourced from the container
+    pub async fn resolve_and_call<F, Args>(&self, mut handler: F) -> F::Output
+    where
+        F: Handler<Args>,
+        Args: LocalResolver + 'static,
+    {
+        let args = self.resolve_all().await;
+        handler.call(args).await
+    }
+
+    /// Given a tuple of types, this method will try to resolve them
+    /// by using a resolver or cloning an existing instance in the container
+    ///
+    /// Unlike `resolve_all`, an error naming the type that could not be resolved
+    /// is returned instead of panicking
+    pub async fn try_resolve_all<Args: LocalResolver>(&self) -> Result<Args, ResolveError> {
+        Args::try_resolve(self).await
+    }
+
+    /// Takes an async function or closure and execute it
+    /// Require arguments are resolve either by a resolver or sourced from the container
+    ///
+    /// Unlike `resolve_and_call`, an error naming the type that could not be resolved
+    /// is returned instead of panicking
+    pub async fn try_resolve_and_call<F, Args>(
+        &self,
+        mut handler: F,
+    ) -> Result<F::Output, ResolveError>
+    where
+        F: Handler<Args>,
+        Args: LocalResolver + 'static,
+    {
+        let args = self.try_resolve_all().await?;
+        Ok(handler.call(args).await)
+    }
+}
+
+// Zero argument
+#[async_trait::async_trait(?Send)]
+impl LocalResolver for () {
+    async fn resolve(_: &LocalServiceContainer) -> Self {}
+}
+
+// 1 argument
+#[async_trait::async_trait(?Send)]
+impl<A: Clone + 'static> LocalResolver for (A,) {
+    async fn resolve(c: &LocalServiceContainer) -> Self {
+        (c.get_type::<A>()
+            .await
+            .unwrap_or_else(|| panic!("could not resolve: {}", type_name::<A>())),)
+    }
+
+    async fn try_resolve(c: &LocalServiceContainer) -> Result<Self, ResolveError> {
+        Ok((c.try_get_type::<A>().await?,))
+    }
+}
+
+/// Same as the `Resolver` tuples but resolving from a `LocalServiceContainer`
+macro_rules! local_tuple_from_resolvable {
+    ($($T: ident),*) => {
+        #[async_trait::async_trait(?Send)]
+        impl<$($T: Clone + 'static),+> LocalResolver for ($($T,)+) {
+            async fn resolve(c: &LocalServiceContainer) -> Self {
+                ($(c.get_type::<$T>().await.unwrap_or_else(|| panic!("could not resolve: {}", type_name::<$T>()))),+)
+            }
+
+            async fn try_resolve(c: &LocalServiceContainer) -> Result<Self, ResolveError> {
+                Ok(($(c.try_get_type::<$T>().await?),+))
+            }
+        }
+    };
+}
+
+local_tuple_from_resolvable! { A, B }
+local_tuple_from_resolvable! { A, B, C }
+local_tuple_from_resolvable! { A, B, C, D }
+local_tuple_from_resolvable! { A, B, C, D, E }
+local_tuple_from_resolvable! { A, B, C, D, E, F }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G, H }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G, H, I }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G, H, I, J }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G, H, I, J, K }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G, H, I, J, K, L }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G, H, I, J, K, L, M }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G, H, I, J, K, L, M, N }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G, H, I, J, K, L, M, N, O }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P }
+local_tuple_from_resolvable! { A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q }
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Default)]
+    struct Cache(Rc<RefCell<Vec<u32>>>);
+
+    #[derive(Debug, Clone)]
+    struct Handle(Rc<Cell<u8>>);
+
+    #[async_trait::async_trait(?Send)]
+    impl LocalResolver for Handle {
+        async fn resolve(container: &LocalServiceContainer) -> Self {
+            let cache = container.get_type::<Cache>().await.unwrap();
+            cache.0.borrow_mut().push(1);
+            Self(Rc::new(Cell::new(cache.0.borrow().len() as u8)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_values() {
+        let container = LocalServiceContainer::new();
+        container.set_type(Cache::default()).await;
+        container.set(Cell::new(5_u8)).await;
+        container.resolvable::<Handle>().await;
+
+        let handle = container.get_type::<Handle>().await.unwrap();
+        assert_eq!(handle.0.get(), 1);
+        assert_eq!(container.get_type::<Handle>().await.unwrap().0.get(), 2);
+        assert_eq!(container.get::<Cell<u8>>().await.unwrap().get(), 5);
+
+        let len = container
+            .resolve_and_call(async |cache: Cache, handle: Handle| {
+                cache.0.borrow().len() + handle.0.get() as usize
+            })
+            .await;
+        assert_eq!(len, 6);
+
+        assert!(container.forget_type::<Cache>().await.removed_instance());
+        assert!(container.try_get_type::<Cache>().await.is_err());
+        assert!(matches!(
+            container
+                .try_resolve_and_call(async |cache: Cache| cache.0.borrow().len())
+                .await,
+            Err(ResolveError::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_local_resolver_once() {
+        let calls = Rc::new(Cell::new(0));
+        let container = LocalServiceContainer::new();
+        let counter = calls.clone();
+        container
+            .resolver_once(move |_| {
+                counter.set(counter.get() + 1);
+                async { Rc::new(7_u8) }
+            })
+            .await;
+        container
+            .soft_resolver_once(|_| async { Rc::new(8_u8) })
+            .await;
+
+        assert_eq!(container.get_type::<Rc<u8>>().await.as_deref(), Some(&7));
+        assert_eq!(container.get_type::<Rc<u8>>().await.as_deref(), Some(&7));
+        assert_eq!(calls.get(), 1);
+
+        assert!(container.forget_resolver::<Rc<u8>>().await);
+        container.soft_resolver(|_| async { 9_u16 }).await;
+        assert_eq!(container.get_type::<u16>().await, Some(9));
+    }
+
+    #[tokio::test]
+    async fn test_local_fallback() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Shared(&'static str);
+
+        let shared = ServiceContainer::proxy();
+        shared.set_type(Shared("thread safe")).await;
+
+        let parent = LocalServiceContainer::with_fallback(shared.clone());
+        parent.set_type(Rc::new("parent")).await;
+        let child = parent.child();
+        child.set_type(1_u8).await;
+
+        assert_eq!(child.get_type().await, Some(Shared("thread safe")));
+        assert_eq!(
+            child.get_type::<Rc<&str>>().await.as_deref(),
+            Some(&"parent")
+        );
+        assert_eq!(parent.get_type::<u8>().await, None);
+        assert_eq!(child.parent().unwrap().id(), parent.id());
+        assert_eq!(parent.fallback().unwrap().id(), shared.id());
+        assert_eq!(
+            LocalServiceContainer::new().get_type::<Shared>().await,
+            None
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Resolution metrics collected by the container
+//!
+//! When the "metrics" feature is disabled, the recorder is a zero sized
+//! type and every hook is a no-op.
+
+#[cfg(feature = "metrics")]
+pub use enabled::{Histogram, ResolutionStats, TypeStats};
+
+#[cfg(feature = "metrics")]
+pub(crate) use enabled::{Metrics, Stopwatch};
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) use disabled::{Metrics, Stopwatch};
+
+/// The layer of the lookup chain that satisfied a lookup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Layer {
+    Local,
+    Parent,
+    Task,
+    Global,
+}
+
+impl std::fmt::Display for Layer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let layer = match self {
+            Self::Local => "local",
+            Self::Parent => "parent",
+            Self::Task => "task",
+            Self::Global => "global",
+        };
+        write!(f, "{layer}")
+    }
+}
+
+#[cfg(feature = "metrics")]
+mod enabled {
+    use std::{
+        any::TypeId,
+        collections::HashMap,
+        fmt::Write,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use super::Layer;
+
+    /// Name, help text and accessor of an exported metric
+    type Metric<V> = (&'static str, &'static str, fn(&TypeStats) -> V);
+    type HistogramMetric = (&'static str, &'static str, fn(&TypeStats) -> &Histogram);
+
+    /// Upper bounds, in seconds, of the latency histogram buckets
+    const BUCKETS: [f64; 8] = [0.00001, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.1, 1.0];
+
+    /// A latency histogram with cumulative buckets
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct Histogram {
+        counts: [u64; BUCKETS.len()],
+        sum: f64,
+        count: u64,
+    }
+
+    impl Histogram {
+        fn observe(&mut self, elapsed: Duration) {
+            let seconds = elapsed.as_secs_f64();
+            for (count, bound) in self.counts.iter_mut().zip(BUCKETS) {
+                if seconds <= bound {
+                    *count += 1;
+                }
+            }
+            self.sum += seconds;
+            self.count += 1;
+        }
+
+        /// Returns the upper bound, in seconds, of each bucket along with the
+        /// number of observations less than or equal to it
+        pub fn buckets(&self) -> Vec<(f64, u64)> {
+            BUCKETS.into_iter().zip(self.counts).collect()
+        }
+
+        /// The sum of all observations in seconds
+        pub fn sum(&self) -> f64 {
+            self.sum
+        }
+
+        /// The number of observations
+        pub fn count(&self) -> u64 {
+            self.count
+        }
+    }
+
+    /// Counters and latencies of a single type
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct TypeStats {
+        type_name: &'static str,
+        lookups: u64,
+        resolver_invocations: u64,
+        misses: u64,
+        global_fallbacks: u64,
+        lookup_latency: Histogram,
+        resolver_latency: Histogram,
+    }
+
+    impl TypeStats {
+        /// The full type name
+        pub fn type_name(&self) -> &'static str {
+            self.type_name
+        }
+
+        /// Number of times the type was requested from the container
+        pub fn lookups(&self) -> u64 {
+            self.lookups
+        }
+
+        /// Number of times a resolver registered in the container was called
+        /// to produce an instance of the type
+        pub fn resolver_invocations(&self) -> u64 {
+            self.resolver_invocations
+        }
+
+        /// Number of lookups that did not find an instance of the type
+        pub fn misses(&self) -> u64 {
+            self.misses
+        }
+
+        /// Number of lookups satisfied by the global container
+        pub fn global_fallbacks(&self) -> u64 {
+            self.global_fallbacks
+        }
+
+        /// Latency of the lookups
+        pub fn lookup_latency(&self) -> &Histogram {
+            &self.lookup_latency
+        }
+
+        /// Latency of the resolver invocations
+        pub fn resolver_latency(&self) -> &Histogram {
+            &self.resolver_latency
+        }
+    }
+
+    /// A snapshot of the resolution metrics of a container
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct ResolutionStats {
+        container_id: u64,
+        types: Vec<TypeStats>,
+    }
+
+    impl ResolutionStats {
+        /// The ID of the container the metrics were collected from
+        pub fn container_id(&self) -> u64 {
+            self.container_id
+        }
+
+        /// The metrics of every type, sorted by type name
+        pub fn types(&self) -> &[TypeStats] {
+            &self.types
+        }
+
+        /// Returns the metrics of the type if it was ever requested or resolved
+        pub fn get<T: 'static>(&self) -> Option<&TypeStats> {
+            let name = std::any::type_name::<T>();
+            self.types.iter().find(|stats| stats.type_name == name)
+        }
+
+        /// Renders the metrics using the Prometheus text exposition format
+        pub fn to_prometheus(&self) -> String {
+            let mut output = String::new();
+
+            let counters: [Metric<u64>; 4] = [
+                (
+                    "busybody_lookups_total",
+                    "Number of lookups per type",
+                    TypeStats::lookups,
+                ),
+                (
+                    "busybody_resolver_invocations_total",
+                    "Number of resolver invocations per type",
+                    TypeStats::resolver_invocations,
+                ),
+                (
+                    "busybody_misses_total",
+                    "Number of lookups that did not find the type",
+                    TypeStats::misses,
+                ),
+                (
+                    "busybody_global_fallbacks_total",
+                    "Number of lookups satisfied by the global container",
+                    TypeStats::global_fallbacks,
+                ),
+            ];
+
+            for (name, help, value) in counters {
+                _ = writeln!(output, "# HELP {name} {help}");
+                _ = writeln!(output, "# TYPE {name} counter");
+                for stats in &self.types {
+                    _ = writeln!(output, "{name}{{{}}} {}", self.labels(stats), value(stats));
+                }
+            }
+
+            let histograms: [HistogramMetric; 2] = [
+                (
+                    "busybody_lookup_duration_seconds",
+                    "Latency of the lookups per type",
+                    TypeStats::lookup_latency,
+                ),
+                (
+                    "busybody_resolver_duration_seconds",
+                    "Latency of the resolver invocations per type",
+                    TypeStats::resolver_latency,
+                ),
+            ];
+
+            for (name, help, histogram) in histograms {
+                _ = writeln!(output, "# HELP {name} {help}");
+                _ = writeln!(output, "# TYPE {name} histogram");
+                for stats in &self.types {
+                    let labels = self.labels(stats);
+                    let histogram = histogram(stats);
+                    for (bound, count) in histogram.buckets() {
+                        _ = writeln!(output, "{name}_bucket{{{labels},le=\"{bound}\"}} {count}");
+                    }
+                    _ = writeln!(
+                        output,
+                        "{name}_bucket{{{labels},le=\"+Inf\"}} {}",
+                        histogram.count()
+                    );
+                    _ = writeln!(output, "{name}_sum{{{labels}}} {}", histogram.sum());
+                    _ = writeln!(output, "{name}_count{{{labels}}} {}", histogram.count());
+                }
+            }
+
+            output
+        }
+
+        fn labels(&self, stats: &TypeStats) -> String {
+            format!(
+                "container=\"{}\",type=\"{}\"",
+                self.container_id,
+                escape(stats.type_name)
+            )
+        }
+    }
+
+    fn escape(value: &str) -> String {
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    }
+
+    /// Measures the time elapsed since it was started
+    pub(crate) struct Stopwatch(Instant);
+
+    impl Stopwatch {
+        pub(crate) fn start() -> Self {
+            Self(Instant::now())
+        }
+    }
+
+    /// Records the metrics of a single container
+    #[derive(Default, Clone)]
+    pub(crate) struct Metrics {
+        types: Arc<Mutex<HashMap<TypeId, TypeStats>>>,
+    }
+
+    impl Metrics {
+        fn with<T: 'static>(&self, update: impl FnOnce(&mut TypeStats)) {
+            if let Ok(mut lock) = self.types.lock() {
+                let stats = lock.entry(TypeId::of::<T>()).or_default();
+                stats.type_name = std::any::type_name::<T>();
+                update(stats);
+            }
+        }
+
+        pub(crate) fn record_lookup<T: 'static>(&self, watch: Stopwatch, layer: Option<Layer>) {
+            let elapsed = watch.0.elapsed();
+            self.with::<T>(|stats| {
+                stats.lookups += 1;
+                stats.lookup_latency.observe(elapsed);
+                match layer {
+                    None => stats.misses += 1,
+                    Some(Layer::Global) => stats.global_fallbacks += 1,
+                    Some(_) => (),
+                }
+            });
+        }
+
+        pub(crate) fn record_resolver<T: 'static>(&self, watch: Stopwatch) {
+            let elapsed = watch.0.elapsed();
+            self.with::<T>(|stats| {
+                stats.resolver_invocations += 1;
+                stats.resolver_latency.observe(elapsed);
+            });
+        }
+
+        pub(crate) fn snapshot(&self, container_id: u64) -> ResolutionStats {
+            let mut types = self
+                .types
+                .lock()
+                .map(|lock| lock.values().cloned().collect::<Vec<_>>())
+                .unwrap_or_default();
+            types.sort_by_key(|stats| stats.type_name);
+
+            ResolutionStats {
+                container_id,
+                types,
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod disabled {
+    use super::Layer;
+
+    pub(crate) struct Stopwatch;
+
+    impl Stopwatch {
+        pub(crate) fn start() -> Self {
+            Self
+        }
+    }
+
+    #[derive(Default, Clone)]
+    pub(crate) struct Metrics;
+
+    impl Metrics {
+        pub(crate) fn record_lookup<T: 'static>(&self, _: Stopwatch, _: Option<Layer>) {}
+
+        pub(crate) fn record_resolver<T: 'static>(&self, _: Stopwatch) {}
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod test {
+    use crate::{ServiceContainer, helpers::service_container};
+
+    #[derive(Debug, Clone)]
+    struct GlobalOnly;
+
+    #[derive(Debug, Clone)]
+    struct Missing;
+
+    #[tokio::test]
+    async fn test_stats() {
+        service_container().set_type(GlobalOnly).await;
+
+        let container = ServiceContainer::proxy();
+        container.resolver(|_| async { 42_u16 }).await;
+
+        for _ in 0..3 {
+            container.get_type::<u16>().await;
+        }
+        container.get_type::<Missing>().await;
+        container.get_type::<GlobalOnly>().await;
+
+        let stats = container.stats();
+        let u16_stats = stats.get::<u16>().unwrap();
+        assert_eq!(u16_stats.lookups(), 3);
+        assert_eq!(u16_stats.resolver_invocations(), 3);
+        assert_eq!(u16_stats.misses(), 0);
+        assert_eq!(u16_stats.lookup_latency().count(), 3);
+        assert_eq!(u16_stats.resolver_latency().count(), 3);
+
+        let missing = stats.get::<Missing>().unwrap();
+        assert_eq!(missing.lookups(), 1);
+        assert_eq!(missing.misses(), 1);
+
+        let global = stats.get::<GlobalOnly>().unwrap();
+        assert_eq!(global.global_fallbacks(), 1);
+        assert_eq!(global.resolver_invocations(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_export() {
+        let container = ServiceContainer::proxy();
+        container.resolver(|_| async { 7_i16 }).await;
+        container.get_type::<i16>().await;
+
+        let output = container.stats().to_prometheus();
+        let labels = format!("container=\"{}\",type=\"i16\"", container.id());
+
+        assert!(output.contains("# TYPE busybody_lookups_total counter"));
+        assert!(output.contains(&format!("busybody_lookups_total{{{labels}}} 1")));
+        assert!(output.contains(&format!(
+            "busybody_resolver_invocations_total{{{labels}}} 1"
+        )));
+        assert!(output.contains("# TYPE busybody_lookup_duration_seconds histogram"));
+        assert!(output.contains(&format!(
+            "busybody_lookup_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 1"
+        )));
+        assert!(output.contains(&format!(
+            "busybody_resolver_duration_seconds_count{{{labels}}} 1"
+        )));
+    }
+}
@@ -0,0 +1,327 @@
+//! Pools of reusable objects that can be injected
+//!
+//! A `Pool<T>` creates objects with its factory, up to its maximum size, and
+//! keeps the ones returned to it for the next checkout. Register it with
+//! `ServiceContainerBuilder::pool` and inject `Pool<T>` where objects are needed.
+//!
+//! ```rust
+//! # use busybody::*;
+//! # use std::time::Duration;
+//! #[derive(Debug)]
+//! struct Connection;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let container = ServiceContainerBuilder::new_proxy()
+//!     .pool(
+//!         PoolOptions::new()
+//!             .max_size(4)
+//!             .idle_timeout(Duration::from_secs(60)),
+//!         || async { Connection },
+//!     )
+//!     .await
+//!     .build();
+//!
+//! container
+//!     .resolve_and_call(async |pool: Pool<Connection>| {
+//!         let connection = pool.get().await;
+//!         println!("using {:?}", *connection);
+//!     })
+//!     .await;
+//! # }
+//! ```
+
+use std::{
+    fmt::Debug,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+
+use crate::runtime::{Semaphore, SemaphoreGuardArc};
+
+type PoolFactory<T> = dyn Fn() -> BoxFuture<'static, T> + Send + Sync;
+
+type HealthCheck<T> = dyn for<'a> Fn(&'a T) -> BoxFuture<'a, bool> + Send + Sync;
+
+/// The configuration of a pool
+pub struct PoolOptions<T> {
+    max_size: usize,
+    idle_timeout: Option<Duration>,
+    health_check: Option<Arc<HealthCheck<T>>>,
+}
+
+impl<T> PoolOptions<T> {
+    /// At most ten (10) objects, kept until they are dropped by a health check
+    pub fn new() -> Self {
+        Self {
+            max_size: 10,
+            idle_timeout: None,
+            health_check: None,
+        }
+    }
+
+    /// Sets the maximum number of objects checked out at the same time
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size.max(1);
+        self
+    }
+
+    /// Objects idle for longer are dropped instead of being checked out
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Called on idle objects before they are checked out.
+    /// Objects failing the check are dropped
+    pub fn health_check(
+        mut self,
+        check: impl for<'a> Fn(&'a T) -> BoxFuture<'a, bool> + Send + Sync + 'static,
+    ) -> Self {
+        self.health_check = Some(Arc::new(check));
+        self
+    }
+}
+
+impl<T> Default for PoolOptions<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Idle<T> {
+    value: T,
+    since: Instant,
+}
+
+struct Inner<T> {
+    factory: Box<PoolFactory<T>>,
+    options: PoolOptions<T>,
+    idle: Mutex<Vec<Idle<T>>>,
+    permits: Arc<Semaphore>,
+}
+
+impl<T> Inner<T> {
+    fn idle(&self) -> std::sync::MutexGuard<'_, Vec<Idle<T>>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes the most recently returned object that did not time out
+    fn take_idle(&self) -> Option<T> {
+        let mut idle = self.idle();
+        if let Some(timeout) = self.options.idle_timeout {
+            idle.retain(|entry| entry.since.elapsed() < timeout);
+        }
+
+        idle.pop().map(|entry| entry.value)
+    }
+}
+
+/// A pool of objects created by a factory. Clones share the same objects
+pub struct Pool<T>(Arc<Inner<T>>);
+
+impl<T: Send + Sync + 'static> Pool<T> {
+    pub fn new<F>(options: PoolOptions<T>, factory: impl Fn() -> F + Send + Sync + 'static) -> Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        Self(Arc::new(Inner {
+            factory: Box::new(move || Box::pin(factory())),
+            permits: Arc::new(Semaphore::new(options.max_size)),
+            options,
+            idle: Mutex::new(Vec::new()),
+        }))
+    }
+
+    /// Checks out an object, waiting while the maximum number of objects is checked out
+    ///
+    /// An idle object is reused when one passes the health check, otherwise the
+    /// factory creates a new one. The object returns to the pool when the guard is dropped
+    pub async fn get(&self) -> PoolGuard<T> {
+        let permit = self.0.permits.acquire_arc().await;
+
+        while let Some(value) = self.0.take_idle() {
+            let healthy = match &self.0.options.health_check {
+                Some(check) => check(&value).await,
+                None => true,
+            };
+            if healthy {
+                return self.guard(value, permit);
+            }
+        }
+
+        let value = (self.0.factory)().await;
+        self.guard(value, permit)
+    }
+
+    fn guard(&self, value: T, permit: SemaphoreGuardArc) -> PoolGuard<T> {
+        PoolGuard {
+            value: Some(value),
+            pool: self.0.clone(),
+            _permit: permit,
+        }
+    }
+}
+
+impl<T> Pool<T> {
+    /// The maximum number of objects checked out at the same time
+    pub fn max_size(&self) -> usize {
+        self.0.options.max_size
+    }
+
+    /// The number of objects waiting to be checked out
+    pub fn idle_count(&self) -> usize {
+        self.0.idle().len()
+    }
+}
+
+impl<T> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Debug for Pool<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pool")
+            .field("max_size", &self.max_size())
+            .field("idle", &self.idle_count())
+            .finish_non_exhaustive()
+    }
+}
+
+/// An object checked out of a pool. See `Pool::get`
+pub struct PoolGuard<T> {
+    value: Option<T>,
+    pool: Arc<Inner<T>>,
+    _permit: SemaphoreGuardArc,
+}
+
+impl<T> PoolGuard<T> {
+    /// Takes the object out of the pool for good, a new one will be
+    /// created in its place
+    pub fn detach(mut self) -> T {
+        self.value
+            .take()
+            .expect("the object is only taken when the guard is consumed")
+    }
+}
+
+impl<T> Deref for PoolGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+            .as_ref()
+            .expect("the object is only taken when the guard is consumed")
+    }
+}
+
+impl<T> DerefMut for PoolGuard<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.value
+            .as_mut()
+            .expect("the object is only taken when the guard is consumed")
+    }
+}
+
+impl<T> Drop for PoolGuard<T> {
+    fn drop(&mut self) {
+        if let Some(value) = self.value.take() {
+            self.pool.idle().push(Idle {
+                value,
+                since: Instant::now(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::ServiceContainerBuilder;
+
+    #[derive(Debug)]
+    struct Connection {
+        id: usize,
+        healthy: Arc<AtomicBool>,
+    }
+
+    fn connections(created: Arc<AtomicUsize>) -> impl Fn() -> BoxFuture<'static, Connection> {
+        move || {
+            let id = created.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move {
+                Connection {
+                    id,
+                    healthy: Arc::new(AtomicBool::new(true)),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pool_reuses_objects() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let container = ServiceContainerBuilder::new_proxy()
+            .pool(
+                PoolOptions::new()
+                    .max_size(2)
+                    .health_check(|connection: &Connection| {
+                        Box::pin(async { connection.healthy.load(Ordering::SeqCst) })
+                    }),
+                connections(created.clone()),
+            )
+            .await
+            .build();
+
+        let pool = container.get_type::<Pool<Connection>>().await.unwrap();
+        let first = pool.get().await;
+        let second = pool.get().await;
+        assert_eq!((first.id, second.id), (1, 2));
+
+        let waiting = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.get().await.id }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        assert_eq!(waiting.await.unwrap(), 1);
+        assert_eq!(pool.idle_count(), 1);
+
+        second.healthy.store(false, Ordering::SeqCst);
+        drop(second);
+        let id = container
+            .resolve_and_call(async |pool: Pool<Connection>| pool.get().await.id)
+            .await;
+        assert_eq!(id, 1);
+        assert_eq!(pool.idle_count(), 1);
+
+        let detached = pool.get().await.detach();
+        assert_eq!(detached.id, 1);
+        assert_eq!(pool.idle_count(), 0);
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pool_idle_timeout() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new(
+            PoolOptions::new().idle_timeout(Duration::from_millis(10)),
+            connections(created.clone()),
+        );
+
+        drop(pool.get().await);
+        assert_eq!(pool.get().await.id, 1);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(pool.get().await.id, 2);
+        assert_eq!(pool.idle_count(), 1);
+    }
+}
@@ -0,0 +1,201 @@
+use std::{any::Any, fmt::Display, ops::Deref, sync::Arc};
+
+/// The way a type was registered with a container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegistrationKind {
+    /// An instance stored via `set_type`, `set` or `register`
+    Instance,
+    /// A resolver that is called each time the type is requested
+    Resolver,
+    /// A resolver that is called the first time the type is requested
+    ResolverOnce,
+    /// A resolver whose value is cached until it expires
+    ResolverTtl,
+    /// A resolver that was registered only because none existed
+    SoftResolver,
+}
+
+impl Display for RegistrationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            Self::Instance => "instance",
+            Self::Resolver => "resolver",
+            Self::ResolverOnce => "resolver once",
+            Self::ResolverTtl => "resolver ttl",
+            Self::SoftResolver => "soft resolver",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+/// Describes a single registration found while walking a container
+/// and the containers it falls back to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registration {
+    pub(crate) type_name: &'static str,
+    pub(crate) kind: RegistrationKind,
+    pub(crate) container_id: u64,
+    pub(crate) layer: usize,
+    pub(crate) order: u64,
+}
+
+impl Registration {
+    /// The full type name of the registered type
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// How the type was registered
+    pub fn kind(&self) -> RegistrationKind {
+        self.kind
+    }
+
+    /// The ID of the container the registration lives in
+    pub fn container_id(&self) -> u64 {
+        self.container_id
+    }
+
+    /// The position of the container in the lookup chain.
+    /// Zero (0) is the container that was inspected
+    pub fn layer(&self) -> usize {
+        self.layer
+    }
+
+    /// The order in which the type was registered within its container
+    pub fn order(&self) -> u64 {
+        self.order
+    }
+}
+
+impl Display for Registration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "layer: {}, container: {}, order: {}, type: {}, kind: {}",
+            self.layer, self.container_id, self.order, self.type_name, self.kind
+        )
+    }
+}
+
+/// The result of checking if a container knows about a type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presence {
+    /// An instance of the type is stored
+    Stored,
+    /// A resolver can produce an instance of the type
+    Resolvable,
+    /// The type is unknown to the container and its fallbacks
+    Absent,
+}
+
+/// What was removed when forgetting a type
+///
+/// Forgetting never calls a resolver. Instances registered with `set_type`
+/// count as instances, not resolvers
+#[derive(Debug)]
+pub enum Forgotten<T> {
+    /// Nothing was registered for the type
+    Nothing,
+    /// The instance was removed
+    Instance(Removed<T>),
+    /// The resolver was removed without being called
+    Resolver,
+    /// The instance and a resolver were removed
+    Both(Removed<T>),
+}
+
+impl<T> Forgotten<T> {
+    pub(crate) fn new(instance: Option<Removed<T>>, resolver: bool) -> Self {
+        match (instance, resolver) {
+            (None, false) => Self::Nothing,
+            (None, true) => Self::Resolver,
+            (Some(value), false) => Self::Instance(value),
+            (Some(value), true) => Self::Both(value),
+        }
+    }
+
+    /// Returns true if nothing was removed
+    pub fn is_nothing(&self) -> bool {
+        matches!(self, Self::Nothing)
+    }
+
+    /// Returns true if an instance was removed
+    pub fn removed_instance(&self) -> bool {
+        matches!(self, Self::Instance(_) | Self::Both(_))
+    }
+
+    /// Returns true if a resolver was removed
+    pub fn removed_resolver(&self) -> bool {
+        matches!(self, Self::Resolver | Self::Both(_))
+    }
+
+    /// The removed instance
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Self::Instance(value) | Self::Both(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Takes the removed instance
+    pub fn into_value(self) -> Option<Removed<T>> {
+        match self {
+            Self::Instance(value) | Self::Both(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// An instance removed from a container. See `Forgotten`
+#[derive(Debug)]
+pub enum Removed<T> {
+    /// The container held the only reference to the instance
+    Owned(T),
+    /// The instance is still shared, for example with a `Snapshot`
+    Shared(Arc<T>),
+}
+
+impl<T: Send + Sync + 'static> Removed<T> {
+    /// Returns None when the value is not a `T`
+    pub(crate) fn new(value: Arc<dyn Any + Send + Sync>) -> Option<Self> {
+        let value = value.downcast::<T>().ok()?;
+        Some(match Arc::try_unwrap(value) {
+            Ok(value) => Self::Owned(value),
+            Err(value) => Self::Shared(value),
+        })
+    }
+}
+
+impl<T> Removed<T> {
+    /// Returns true if the instance is still shared
+    pub fn is_shared(&self) -> bool {
+        matches!(self, Self::Shared(_))
+    }
+
+    /// Takes the instance, unless it is still shared
+    pub fn into_owned(self) -> Option<T> {
+        match self {
+            Self::Owned(value) => Some(value),
+            Self::Shared(_) => None,
+        }
+    }
+
+    /// Takes the instance as a reference counted value
+    pub fn into_arc(self) -> Arc<T> {
+        match self {
+            Self::Owned(value) => Arc::new(value),
+            Self::Shared(value) => value,
+        }
+    }
+}
+
+impl<T> Deref for Removed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Owned(value) => value,
+            Self::Shared(value) => value,
+        }
+    }
+}
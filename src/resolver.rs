@@ -1,10 +1,19 @@
 use std::any::type_name;
 
-use crate::ServiceContainer;
+use crate::{ResolveError, ServiceContainer};
 
 #[async_trait::async_trait]
 pub trait Resolver {
     async fn resolve(container: &ServiceContainer) -> Self;
+
+    /// Same as `resolve` but returns an error instead of panicking
+    /// when a required type cannot be resolved
+    async fn try_resolve(container: &ServiceContainer) -> Result<Self, ResolveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self::resolve(container).await)
+    }
 }
 
 // Zero argument
@@ -25,6 +34,10 @@ where
             .await
             .unwrap_or_else(|| panic!("could not resolve: {}", type_name::<A>())),)
     }
+
+    async fn try_resolve(c: &ServiceContainer) -> Result<Self, ResolveError> {
+        Ok((c.try_get_type::<A>().await?,))
+    }
 }
 
 /// This macro is repeating what is been done
@@ -34,7 +47,11 @@ macro_rules! tuple_from_resolvable {
         #[async_trait::async_trait]
         impl<$($T: Clone + Send + Sync  + 'static),+> Resolver for ($($T,)+) {
             async fn resolve(c: &ServiceContainer) -> Self {
-                ($(c.get_type::<$T>().await.expect(&format!("could not resolve: {}", type_name::<A>()))),+)
+                ($(c.get_type::<$T>().await.expect(&format!("could not resolve: {}", type_name::<$T>()))),+)
+            }
+
+            async fn try_resolve(c: &ServiceContainer) -> Result<Self, ResolveError> {
+                Ok(($(c.try_get_type::<$T>().await?),+))
             }
         }
     };
@@ -0,0 +1,291 @@
+//! The parts of the container that depend on the async runtime
+//!
+//! Locks and task local values work on any runtime. Timers, detached tasks and
+//! task IDs are provided by the runtime selected with the "tokio" (default) or
+//! "smol" feature. When both are enabled, tokio is used inside a tokio runtime.
+//! Without a runtime to use, timers are woken by one shared thread and detached
+//! tasks run on threads of their own.
+
+use std::{
+    cell::RefCell,
+    pin::{Pin, pin},
+    task::{Context, Poll},
+    thread::LocalKey,
+    time::Duration,
+};
+
+use futures::future::{Either, select};
+
+pub(crate) use async_lock::{Mutex, RwLock, Semaphore, SemaphoreGuardArc};
+
+/// The future did not complete within the allowed time
+#[derive(Debug)]
+pub(crate) struct Elapsed;
+
+/// Runs the future, giving up once the duration has passed
+pub(crate) async fn timeout<F: Future>(duration: Duration, fut: F) -> Result<F::Output, Elapsed> {
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return tokio::time::timeout(duration, fut)
+            .await
+            .map_err(|_| Elapsed);
+    }
+
+    match select(pin!(fut), pin!(sleep(duration))).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(Elapsed),
+    }
+}
+
+/// Waits until the duration has passed
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        tokio::time::sleep(duration).await;
+        return;
+    }
+
+    #[cfg(feature = "smol")]
+    smol::Timer::after(duration).await;
+
+    #[cfg(not(feature = "smol"))]
+    thread_sleep(duration).await;
+}
+
+/// Waits on the shared timer thread, for when no runtime provides timers
+#[cfg(not(feature = "smol"))]
+fn thread_sleep(duration: Duration) -> timer::Timer {
+    timer::Timer::after(duration)
+}
+
+/// One thread waking every pending timer, started by the first timer polled
+#[cfg(not(feature = "smol"))]
+mod timer {
+    use std::{
+        collections::BTreeMap,
+        pin::Pin,
+        sync::{Condvar, Mutex, MutexGuard, OnceLock, PoisonError},
+        task::{Context, Poll, Waker},
+        time::{Duration, Instant},
+    };
+
+    /// The deadline of a timer and its ID, keeping timers with the same deadline apart
+    pub(super) type TimerKey = (Instant, u64);
+
+    #[derive(Default)]
+    struct Timers {
+        pending: Mutex<(u64, BTreeMap<TimerKey, Waker>)>,
+        changed: Condvar,
+    }
+
+    impl Timers {
+        fn lock(&self) -> MutexGuard<'_, (u64, BTreeMap<TimerKey, Waker>)> {
+            self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        fn run(&self) {
+            let mut lock = self.lock();
+            loop {
+                let now = Instant::now();
+                let Some(&(deadline, _)) = lock.1.keys().next() else {
+                    lock = self
+                        .changed
+                        .wait(lock)
+                        .unwrap_or_else(PoisonError::into_inner);
+                    continue;
+                };
+
+                if deadline > now {
+                    lock = self
+                        .changed
+                        .wait_timeout(lock, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                    continue;
+                }
+
+                let pending = lock.1.split_off(&(now, u64::MAX));
+                let expired = std::mem::replace(&mut lock.1, pending);
+                drop(lock);
+                for waker in expired.into_values() {
+                    waker.wake();
+                }
+                lock = self.lock();
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub(super) fn pending(key: &TimerKey) -> bool {
+        timers().lock().1.contains_key(key)
+    }
+
+    fn timers() -> &'static Timers {
+        static TIMERS: OnceLock<Timers> = OnceLock::new();
+
+        TIMERS.get_or_init(|| {
+            std::thread::Builder::new()
+                .name("busybody-timer".to_string())
+                .spawn(|| timers().run())
+                .expect("could not start the timer thread");
+            Timers::default()
+        })
+    }
+
+    /// Completes once its deadline has passed
+    pub(crate) struct Timer {
+        deadline: Instant,
+        pub(super) key: Option<TimerKey>,
+    }
+
+    impl Timer {
+        pub(crate) fn after(duration: Duration) -> Self {
+            Self {
+                deadline: Instant::now() + duration,
+                key: None,
+            }
+        }
+    }
+
+    impl Future for Timer {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if Instant::now() >= self.deadline {
+                if let Some(key) = self.key.take() {
+                    timers().lock().1.remove(&key);
+                }
+                return Poll::Ready(());
+            }
+
+            let timers = timers();
+            let mut lock = timers.lock();
+            let deadline = self.deadline;
+            let key = *self.key.get_or_insert_with(|| {
+                lock.0 += 1;
+                (deadline, lock.0)
+            });
+            lock.1.insert(key, cx.waker().clone());
+            let earliest = lock.1.keys().next() == Some(&key);
+            drop(lock);
+
+            if earliest {
+                timers.changed.notify_one();
+            }
+            Poll::Pending
+        }
+    }
+
+    impl Drop for Timer {
+        fn drop(&mut self) {
+            if let Some(key) = self.key.take() {
+                timers().lock().1.remove(&key);
+            }
+        }
+    }
+}
+
+/// Runs the future in the background.
+/// Without a runtime to spawn it on, the future runs on a thread of its own
+pub(crate) fn spawn_detached(fut: impl Future<Output = ()> + Send + 'static) {
+    #[cfg(feature = "tokio")]
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        handle.spawn(fut);
+        return;
+    }
+
+    #[cfg(feature = "smol")]
+    smol::spawn(fut).detach();
+
+    #[cfg(not(feature = "smol"))]
+    std::thread::spawn(move || futures::executor::block_on(fut));
+}
+
+/// A stack of values, the innermost being the current one
+pub(crate) type LocalStack<T> = LocalKey<RefCell<Vec<T>>>;
+
+/// Returns the innermost value of the stack
+pub(crate) fn current<T: Clone + 'static>(key: &'static LocalStack<T>) -> Option<T> {
+    key.with_borrow(|stack| stack.last().cloned())
+}
+
+/// Pushes the value on the stack each time the future is polled and pops it
+/// afterwards, making it a task local value on any runtime.
+/// Tasks spawned by the future do not see the value
+pub(crate) struct Scoped<T: 'static, F> {
+    key: &'static LocalStack<T>,
+    value: T,
+    future: Pin<Box<F>>,
+}
+
+impl<T: 'static, F> Scoped<T, F> {
+    pub(crate) fn new(key: &'static LocalStack<T>, value: T, future: F) -> Self {
+        Self {
+            key,
+            value,
+            future: Box::pin(future),
+        }
+    }
+}
+
+// The value is never pinned, only the future is and it lives in a box
+impl<T: 'static, F> Unpin for Scoped<T, F> {}
+
+impl<T: Clone + 'static, F: Future> Future for Scoped<T, F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        struct Pop<T: 'static>(&'static LocalStack<T>);
+
+        impl<T: 'static> Drop for Pop<T> {
+            fn drop(&mut self) {
+                self.0.with_borrow_mut(|stack| stack.pop());
+            }
+        }
+
+        let this = self.get_mut();
+        this.key
+            .with_borrow_mut(|stack| stack.push(this.value.clone()));
+        let _pop = Pop(this.key);
+
+        this.future.as_mut().poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_timers_and_tasks_without_a_runtime() {
+        let (sender, receiver) = mpsc::channel();
+        spawn_detached(async move {
+            sleep(Duration::from_millis(1)).await;
+            sender.send(()).unwrap();
+        });
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let result = futures::executor::block_on(timeout(
+            Duration::from_millis(5),
+            futures::future::pending::<()>(),
+        ));
+        assert!(result.is_err());
+    }
+
+    #[cfg(not(feature = "smol"))]
+    #[test]
+    fn test_timers_share_one_thread() {
+        let sleeps = (0..50).map(|i| sleep(Duration::from_millis(i % 5)));
+        futures::executor::block_on(futures::future::join_all(sleeps));
+
+        let mut timer = Box::pin(thread_sleep(Duration::from_secs(60)));
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        assert!(timer.as_mut().poll(&mut cx).is_pending());
+        let key = timer.key.unwrap();
+        assert!(timer::pending(&key));
+        drop(timer);
+        assert!(!timer::pending(&key));
+    }
+}
@@ -27,6 +27,7 @@ mod test {
     }
 
     #[tokio::test]
+    #[allow(clippy::useless_conversion)]
     async fn test_creating_from_arc() {
         let service: Service<i32> = Arc::new(7).into();
         assert_eq!(*service, 7);
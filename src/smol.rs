@@ -0,0 +1,64 @@
+//! smol integration
+//!
+//! smol does not identify its tasks, so task proxies only work within futures
+//! wrapped by `task_scope`. `spawn` does that for every task it starts.
+
+use crate::task_scope;
+
+/// Spawns the future on smol's global executor as a task with its own task scope
+///
+/// ```rust
+/// smol::block_on(async {
+///     let id = busybody::smol::spawn(async {
+///         busybody::helpers::make_task_proxy().unwrap().id()
+///     })
+///     .await;
+///     assert_ne!(id, 0);
+/// });
+/// ```
+pub fn spawn<T: Send + 'static>(fut: impl Future<Output = T> + Send + 'static) -> smol::Task<T> {
+    smol::spawn(task_scope(fut))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{ResolveError, ServiceContainer, helpers};
+
+    #[test]
+    fn test_task_proxies_and_timeouts() {
+        smol::block_on(async {
+            let id = spawn(async {
+                let first = helpers::make_task_proxy().unwrap();
+                let second = helpers::make_task_proxy().unwrap();
+                assert_eq!(first.id(), second.id());
+
+                first.set_type(9_u16).await;
+                assert_eq!(ServiceContainer::proxy().get_type().await, Some(9_u16));
+                first.id()
+            })
+            .await;
+
+            assert!(
+                ServiceContainer::task_scopes()
+                    .iter()
+                    .all(|scope| scope.container_id() != id)
+            );
+
+            let container = ServiceContainer::proxy();
+            container
+                .resolver_with_timeout(Duration::from_millis(10), |_| async {
+                    smol::Timer::after(Duration::from_secs(5)).await;
+                    1_u64
+                })
+                .await;
+
+            assert!(matches!(
+                container.try_get_type::<u64>().await,
+                Err(ResolveError::Timeout { .. })
+            ));
+        });
+    }
+}
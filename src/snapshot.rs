@@ -0,0 +1,30 @@
+use std::fmt::Debug;
+
+use crate::container::Registrations;
+
+/// The registrations of a container at a point in time
+///
+/// Created by `ServiceContainer::snapshot` and reverted to with
+/// `ServiceContainer::restore`, which only accepts snapshots of the same
+/// container. Instances and resolvers are shared with the container,
+/// taking a snapshot does not clone them
+#[derive(Clone)]
+pub struct Snapshot {
+    pub(crate) container_id: u64,
+    pub(crate) registrations: Registrations,
+}
+
+impl Snapshot {
+    /// The ID of the container the snapshot was taken from
+    pub fn container_id(&self) -> u64 {
+        self.container_id
+    }
+}
+
+impl Debug for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("container_id", &self.container_id)
+            .finish_non_exhaustive()
+    }
+}
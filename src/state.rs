@@ -0,0 +1,68 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::runtime::RwLock;
+
+pub type StateReadGuard<'a, T> = async_lock::RwLockReadGuard<'a, T>;
+pub type StateWriteGuard<'a, T> = async_lock::RwLockWriteGuard<'a, T>;
+
+/// A service that can be shared and modified
+///
+/// Clones share the same value. Register it with `ServiceContainer::set_state`
+/// and inject `State<T>` wherever the value is needed
+///
+/// ```rust
+/// # use busybody::*;
+/// # #[tokio::main]
+/// # async fn main() {
+/// let container = ServiceContainer::proxy();
+/// container.set_state(0_u32).await;
+///
+/// container
+///     .resolve_and_call(async |hits: State<u32>| *hits.write().await += 1)
+///     .await;
+///
+/// let hits = container.get_type::<State<u32>>().await.unwrap();
+/// assert_eq!(*hits.read().await, 1);
+/// # }
+/// ```
+pub struct State<T>(Arc<RwLock<T>>);
+
+impl<T> State<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(RwLock::new(value)))
+    }
+
+    /// Locks the value for reading
+    pub async fn read(&self) -> StateReadGuard<'_, T> {
+        self.0.read().await
+    }
+
+    /// Locks the value for writing
+    pub async fn write(&self) -> StateWriteGuard<'_, T> {
+        self.0.write().await
+    }
+}
+
+impl<T> Clone for State<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Default> Default for State<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for State<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Debug> Debug for State<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("State").field(&self.0).finish()
+    }
+}
@@ -0,0 +1,275 @@
+//! Task scopes shared by the task proxies created within the same task
+//!
+//! A scope belongs to its task, not to the task proxies pointing at it. Values set
+//! through a task proxy are kept after it is dropped, and a task proxy escaping
+//! the task does not keep them alive.
+//!
+//! Futures wrapped by `task_scope` get their own ID, on any runtime, and their
+//! scopes are removed and emptied as soon as the future completes or is dropped.
+//! Other tasks are identified by the ID tokio gives them. tokio does not report
+//! when a task ends, so the registry only holds a weak reference to the scope of a
+//! task spawned without `task_scope`: it is dropped with its last task proxy, and
+//! values set through a task proxy are only shared with the task proxies alive at
+//! the same time.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Display,
+    sync::{
+        Arc, Mutex, MutexGuard, OnceLock, PoisonError, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::{container::Container, runtime};
+
+/// Identifies the task a scope belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum TaskId {
+    #[cfg(feature = "tokio")]
+    Tokio(tokio::task::Id),
+    Scoped(u64),
+}
+
+impl Display for TaskId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "tokio")]
+            Self::Tokio(id) => write!(f, "{id}"),
+            Self::Scoped(id) => write!(f, "scoped-{id}"),
+        }
+    }
+}
+
+static NEXT_SCOPED_TASK: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static SCOPED_TASK: RefCell<Vec<TaskId>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Returns the ID of the current task, preferring the one given by `task_scope`
+fn current_task() -> Option<TaskId> {
+    runtime::current(&SCOPED_TASK).or_else(runtime_task)
+}
+
+/// Returns the ID the runtime gave to the current task
+#[cfg(feature = "tokio")]
+fn runtime_task() -> Option<TaskId> {
+    tokio::task::try_id().map(TaskId::Tokio)
+}
+
+#[cfg(not(feature = "tokio"))]
+fn runtime_task() -> Option<TaskId> {
+    None
+}
+
+/// Runs the future as a task of its own for task proxies
+///
+/// Task proxies created while the future runs share one scope, separate from the
+/// scope of the task polling it. The scope is removed and its registrations are
+/// dropped once the future completes or is dropped, even if a task proxy escaped it.
+/// This also gives task proxies to runtimes that do not identify their tasks, such as smol
+///
+/// ```rust
+/// # use busybody::*;
+/// # #[tokio::main]
+/// # async fn main() {
+/// let id = task_scope(async {
+///     let first = helpers::make_task_proxy().unwrap();
+///     let second = helpers::make_task_proxy().unwrap();
+///     assert_eq!(first.id(), second.id());
+///     first.id()
+/// })
+/// .await;
+///
+/// assert!(ServiceContainer::task_scopes().iter().all(|scope| scope.container_id() != id));
+/// # }
+/// ```
+pub async fn task_scope<F: Future>(fut: F) -> F::Output {
+    let task = TaskId::Scoped(NEXT_SCOPED_TASK.fetch_add(1, Ordering::Relaxed));
+    let _guard = TaskGuard(task);
+    runtime::Scoped::new(&SCOPED_TASK, task, fut).await
+}
+
+/// Removes the scopes of the task when dropped
+struct TaskGuard(TaskId);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        let mut lock = registry();
+        let keys = lock
+            .keys()
+            .filter(|(_, task)| *task == self.0)
+            .copied()
+            .collect::<Vec<_>>();
+        let scopes = keys
+            .iter()
+            .filter_map(|key| lock.remove(key))
+            .collect::<Vec<_>>();
+        // Dropping a scope takes the lock
+        drop(lock);
+
+        for scope in scopes {
+            if let Slot::Owned(scope) = scope {
+                scope.container.dispose();
+            }
+        }
+    }
+}
+
+/// The ID of the root container the scope falls back to and the ID of its task
+type TaskKey = (u64, TaskId);
+
+/// How the registry holds a scope
+enum Slot {
+    /// The scope of a future wrapped by `task_scope`, removed by its `TaskGuard`
+    Owned(Arc<TaskScope>),
+    /// The scope of a task the crate cannot see the end of, kept by its task proxies
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    Shared(Weak<TaskScope>),
+}
+
+impl Slot {
+    fn scope(&self) -> Option<Arc<TaskScope>> {
+        match self {
+            Self::Owned(scope) => Some(scope.clone()),
+            Self::Shared(scope) => scope.upgrade(),
+        }
+    }
+}
+
+static TASK_SCOPES: OnceLock<Mutex<HashMap<TaskKey, Slot>>> = OnceLock::new();
+
+fn registry() -> MutexGuard<'static, HashMap<TaskKey, Slot>> {
+    TASK_SCOPES
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The store shared by the task proxies of a task
+pub(crate) struct TaskScope {
+    pub(crate) id: u64,
+    pub(crate) container: Container,
+    key: TaskKey,
+}
+
+impl TaskScope {
+    /// Returns the scope of the current task for the root
+    pub(crate) fn current(root_id: u64) -> Option<Arc<Self>> {
+        let key = (root_id, current_task()?);
+        registry().get(&key).and_then(Slot::scope)
+    }
+
+    /// Returns the scope of the current task for the root, creating it when needed.
+    /// None is returned outside of a task
+    pub(crate) fn current_or_create(root_id: u64) -> Option<Arc<Self>> {
+        let key = (root_id, current_task()?);
+        let mut lock = registry();
+        if let Some(scope) = lock.get(&key).and_then(Slot::scope) {
+            return Some(scope);
+        }
+
+        let scope = Arc::new(Self {
+            id: ulid::Ulid::new().0 as u64,
+            container: Container::default(),
+            key,
+        });
+        let slot = match key.1 {
+            TaskId::Scoped(_) => Slot::Owned(scope.clone()),
+            #[cfg(feature = "tokio")]
+            TaskId::Tokio(_) => Slot::Shared(Arc::downgrade(&scope)),
+        };
+        lock.insert(key, slot);
+
+        Some(scope)
+    }
+
+    /// Describes every task scope
+    pub(crate) fn live() -> Vec<TaskScopeInfo> {
+        let scopes = registry()
+            .iter()
+            .filter_map(|((root_id, task), slot)| {
+                // Not counting the upgraded reference and the one held by the registry
+                let extra = match slot {
+                    Slot::Owned(_) => 2,
+                    Slot::Shared(_) => 1,
+                };
+                Some((*root_id, task.to_string(), slot.scope()?, extra))
+            })
+            .collect::<Vec<_>>();
+
+        // The upgraded references are dropped after the lock is released,
+        // as dropping the last one removes the scope
+        scopes
+            .into_iter()
+            .map(|(root_id, task, scope, extra)| TaskScopeInfo {
+                task,
+                container_id: scope.id,
+                root_id,
+                handles: Arc::strong_count(&scope).saturating_sub(extra),
+            })
+            .collect()
+    }
+}
+
+impl Drop for TaskScope {
+    fn drop(&mut self) {
+        let mut lock = registry();
+        let shared = matches!(
+            lock.get(&self.key),
+            Some(Slot::Shared(scope)) if std::ptr::eq(scope.as_ptr(), self)
+        );
+        if shared {
+            lock.remove(&self.key);
+        }
+        drop(lock);
+
+        self.container.dispose();
+    }
+}
+
+/// Describes a live task scope. See `ServiceContainer::task_scopes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskScopeInfo {
+    pub(crate) task: String,
+    pub(crate) container_id: u64,
+    pub(crate) root_id: u64,
+    pub(crate) handles: usize,
+}
+
+impl TaskScopeInfo {
+    /// The runtime's ID of the task, formatted for display
+    pub fn task(&self) -> &str {
+        &self.task
+    }
+
+    /// The ID of the task proxies sharing the scope
+    pub fn container_id(&self) -> u64 {
+        self.container_id
+    }
+
+    /// The ID of the root container the scope falls back to.
+    /// Zero (0) is the global container
+    pub fn root_id(&self) -> u64 {
+        self.root_id
+    }
+
+    /// The number of task proxies currently pointing at the scope.
+    /// The scope of a future wrapped by `task_scope` is kept when it drops to zero,
+    /// the scope of any other task is dropped
+    pub fn handles(&self) -> usize {
+        self.handles
+    }
+}
+
+impl Display for TaskScopeInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "task: {}, container: {}, root: {}, handles: {}",
+            self.task, self.container_id, self.root_id, self.handles
+        )
+    }
+}
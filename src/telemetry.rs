@@ -0,0 +1,271 @@
+//! Tracing hooks used by the container
+//!
+//! When the "tracing" feature is disabled, every hook compiles down to
+//! the wrapped future or to a no-op.
+
+#[cfg(feature = "tracing")]
+pub(crate) use enabled::*;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use disabled::*;
+
+#[cfg(feature = "tracing")]
+mod enabled {
+    use std::time::Instant;
+
+    use tracing::{Instrument, Span, field::Empty};
+
+    use crate::{ResolveError, ServiceContainer, metrics::Layer, registration::RegistrationKind};
+
+    /// Wraps a type lookup in a span
+    pub(crate) async fn lookup<T, F>(
+        ci: &ServiceContainer,
+        type_name: &'static str,
+        fut: F,
+    ) -> Result<Option<T>, ResolveError>
+    where
+        F: Future<Output = Result<Option<T>, ResolveError>>,
+    {
+        let span = tracing::debug_span!(
+            "busybody::get_type",
+            type_name,
+            container_id = ci.id(),
+            proxy = ci.is_proxy(),
+            task = ci.is_task_proxy(),
+            layer = Empty,
+            source = Empty,
+        );
+        let started = Instant::now();
+        let value = fut.instrument(span.clone()).await;
+
+        span.in_scope(|| {
+            let elapsed_us = started.elapsed().as_micros() as u64;
+            match &value {
+                Ok(found) => {
+                    tracing::debug!(found = found.is_some(), elapsed_us, "lookup completed")
+                }
+                Err(error) => tracing::warn!(%error, elapsed_us, "lookup failed"),
+            }
+        });
+
+        value
+    }
+
+    /// Wraps a resolver invocation in a span
+    pub(crate) async fn resolver<F: Future>(
+        type_name: &'static str,
+        kind: RegistrationKind,
+        fut: F,
+    ) -> F::Output {
+        let span = tracing::debug_span!(
+            "busybody::resolver",
+            type_name,
+            kind = %kind,
+        );
+        let started = Instant::now();
+        let output = fut.instrument(span.clone()).await;
+
+        span.in_scope(|| {
+            tracing::debug!(
+                elapsed_us = started.elapsed().as_micros() as u64,
+                "resolver completed"
+            )
+        });
+
+        output
+    }
+
+    /// Wraps `resolve_all` and `resolve_and_call` in a span
+    pub(crate) async fn call<F: Future>(
+        name: &'static str,
+        ci: &ServiceContainer,
+        type_name: &'static str,
+        fut: F,
+    ) -> F::Output {
+        let span = tracing::debug_span!(
+            "busybody::call",
+            name,
+            type_name,
+            container_id = ci.id(),
+            proxy = ci.is_proxy(),
+            task = ci.is_task_proxy(),
+        );
+        let started = Instant::now();
+        let output = fut.instrument(span.clone()).await;
+
+        span.in_scope(|| {
+            tracing::debug!(
+                elapsed_us = started.elapsed().as_micros() as u64,
+                "{name} completed"
+            )
+        });
+
+        output
+    }
+
+    /// Records which layer of the lookup chain satisfied the current lookup
+    pub(crate) fn record_layer(layer: Layer) {
+        Span::current().record("layer", tracing::field::display(layer));
+    }
+
+    /// Records if the current lookup was served from a stored instance or by
+    /// calling a factory
+    pub(crate) fn record_source(kind: Option<RegistrationKind>) {
+        let source = match kind {
+            Some(RegistrationKind::Instance) | None => "cache",
+            Some(_) => "factory",
+        };
+        Span::current().record("source", source);
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod disabled {
+    use crate::{ResolveError, ServiceContainer, metrics::Layer, registration::RegistrationKind};
+
+    pub(crate) async fn lookup<T, F>(
+        _: &ServiceContainer,
+        _: &'static str,
+        fut: F,
+    ) -> Result<Option<T>, ResolveError>
+    where
+        F: Future<Output = Result<Option<T>, ResolveError>>,
+    {
+        fut.await
+    }
+
+    pub(crate) async fn resolver<F: Future>(
+        _: &'static str,
+        _: RegistrationKind,
+        fut: F,
+    ) -> F::Output {
+        fut.await
+    }
+
+    pub(crate) async fn call<F: Future>(
+        _: &'static str,
+        _: &ServiceContainer,
+        _: &'static str,
+        fut: F,
+    ) -> F::Output {
+        fut.await
+    }
+
+    pub(crate) fn record_layer(_: Layer) {}
+
+    pub(crate) fn record_source(_: Option<RegistrationKind>) {}
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use std::{
+        collections::HashMap,
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicU64, Ordering},
+        },
+    };
+
+    use tracing::{
+        Event, Metadata, Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+    };
+
+    use tracing_core::span::Current;
+
+    use crate::ServiceContainer;
+
+    #[derive(Default, Clone)]
+    struct Recorder {
+        spans: Arc<Mutex<Vec<String>>>,
+        fields: Arc<Mutex<Vec<(String, String)>>>,
+        metadata: Arc<Mutex<HashMap<u64, &'static Metadata<'static>>>>,
+        stack: Arc<Mutex<Vec<u64>>>,
+        next_id: Arc<AtomicU64>,
+    }
+
+    impl Visit for Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.fields
+                .lock()
+                .unwrap()
+                .push((field.name().to_string(), format!("{value:?}")));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.spans
+                .lock()
+                .unwrap()
+                .push(span.metadata().name().to_string());
+            span.record(&mut self.clone());
+
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+            self.metadata.lock().unwrap().insert(id, span.metadata());
+            Id::from_u64(id)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut self.clone());
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut self.clone());
+        }
+
+        fn enter(&self, id: &Id) {
+            self.stack.lock().unwrap().push(id.into_u64());
+        }
+
+        fn exit(&self, _: &Id) {
+            self.stack.lock().unwrap().pop();
+        }
+
+        fn current_span(&self) -> Current {
+            match self.stack.lock().unwrap().last() {
+                Some(id) => Current::new(Id::from_u64(*id), self.metadata.lock().unwrap()[id]),
+                None => Current::none(),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolution_spans() {
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        let container = ServiceContainer::proxy();
+        container.resolver(|_| async { 42_u32 }).await;
+        container.set_type(true).await;
+
+        let child = container.child();
+        assert_eq!(child.get_type::<u32>().await, Some(42));
+        assert_eq!(child.resolve_all::<(bool,)>().await, (true,));
+
+        let spans = recorder.spans.lock().unwrap().clone();
+        assert!(spans.contains(&"busybody::get_type".to_string()));
+        assert!(spans.contains(&"busybody::resolver".to_string()));
+        assert!(spans.contains(&"busybody::call".to_string()));
+
+        let fields = recorder.fields.lock().unwrap().clone();
+        let has = |name: &str, value: &str| {
+            fields
+                .iter()
+                .any(|(n, v)| n == name && v.trim_matches('"') == value)
+        };
+        assert!(has("type_name", "u32"));
+        assert!(has("layer", "parent"));
+        assert!(has("layer", "local"));
+        assert!(has("source", "factory"));
+        assert!(has("source", "cache"));
+        assert!(fields.iter().any(|(n, _)| n == "elapsed_us"));
+    }
+}
@@ -0,0 +1,278 @@
+//! Utilities for testing code that uses the container
+//!
+//! `TestContainer` installs an isolated root container as the implicit container
+//! of the current thread, so `helpers::*` calls made by a test do not touch the
+//! global container. `override_type` temporarily replaces a registration,
+//! `spy_resolver` counts how often a resolver is called and `assert_resolvable`
+//! / `assert_not_resolvable` check what the implicit container can resolve.
+//!
+//! ```rust
+//! # use busybody::{helpers, testing::*};
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let test = TestContainer::new();
+//! helpers::set_type(1_u8).await;
+//!
+//! {
+//!     let _guard = override_type(2_u8).await;
+//!     assert_eq!(helpers::get_type::<u8>().await, Some(2));
+//! }
+//!
+//! assert_eq!(helpers::get_type::<u8>().await, Some(1));
+//! assert_eq!(test.get_type::<u8>().await, Some(1));
+//! # }
+//! ```
+
+use std::{
+    any::type_name,
+    marker::PhantomData,
+    ops::Deref,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use crate::{
+    ServiceContainer,
+    container::{Container, TakenEntries},
+    current, helpers,
+};
+
+/// An isolated root container that the `helpers::*` functions use while it lives
+///
+/// The container is installed for the thread that created it. Tests running on
+/// tokio's default current thread runtime, including the tasks they spawn, will
+/// resolve against it. Dropping the guard restores the previous implicit container
+pub struct TestContainer {
+    container: ServiceContainer,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl TestContainer {
+    /// Creates an isolated root container and installs it on this thread
+    pub fn new() -> Self {
+        let container = ServiceContainer::isolated();
+        current::push_test_container(container.clone());
+
+        Self {
+            container,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Returns the installed container
+    pub fn container(&self) -> ServiceContainer {
+        self.container.clone()
+    }
+}
+
+impl Default for TestContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for TestContainer {
+    type Target = ServiceContainer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.container
+    }
+}
+
+impl Drop for TestContainer {
+    fn drop(&mut self) {
+        current::remove_test_container(self.container.id());
+    }
+}
+
+/// Restores the registrations of a type when dropped. See `override_type`
+#[must_use = "the override is reverted as soon as the guard is dropped"]
+pub struct OverrideGuard {
+    container: Container,
+    previous: Option<TakenEntries>,
+}
+
+impl Drop for OverrideGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            self.container.put_back(previous);
+        }
+    }
+}
+
+/// Replaces the registrations of the type in the implicit container with the value
+///
+/// The previous instance or resolver is restored when the returned guard is dropped
+pub async fn override_type<T: Clone + Send + Sync + 'static>(value: T) -> OverrideGuard {
+    override_type_in(&helpers::service_container(), value).await
+}
+
+/// Same as `override_type` but for the provided container
+pub async fn override_type_in<T: Clone + Send + Sync + 'static>(
+    container: &ServiceContainer,
+    value: T,
+) -> OverrideGuard {
+    let store = container.store();
+    let previous = store.take::<T>().await;
+    container.set_type(value).await;
+
+    OverrideGuard {
+        container: store,
+        previous: Some(previous),
+    }
+}
+
+/// Counts the calls made to a resolver. See `spy_resolver`
+#[derive(Debug, Clone)]
+pub struct ResolverSpy {
+    type_name: &'static str,
+    calls: Arc<AtomicUsize>,
+}
+
+impl ResolverSpy {
+    /// The number of times the resolver was called
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Panics if the resolver was not called exactly `times` times
+    pub fn assert_called(&self, times: usize) {
+        let calls = self.calls();
+        assert_eq!(
+            calls, times,
+            "expected the resolver of {} to be called {times} time(s), it was called {calls} time(s)",
+            self.type_name
+        );
+    }
+}
+
+/// Registers a resolver in the implicit container and returns a spy
+/// counting how often it is called
+pub async fn spy_resolver<T: Send + Sync + 'static, F>(
+    callback: impl FnMut(ServiceContainer) -> F + Send + Sync + 'static,
+) -> ResolverSpy
+where
+    F: Future<Output = T> + Send + 'static,
+{
+    spy_resolver_in(&helpers::service_container(), callback).await
+}
+
+/// Same as `spy_resolver` but for the provided container
+pub async fn spy_resolver_in<T: Send + Sync + 'static, F>(
+    container: &ServiceContainer,
+    mut callback: impl FnMut(ServiceContainer) -> F + Send + Sync + 'static,
+) -> ResolverSpy
+where
+    F: Future<Output = T> + Send + 'static,
+{
+    let spy = ResolverSpy {
+        type_name: type_name::<T>(),
+        calls: Arc::default(),
+    };
+
+    let calls = spy.calls.clone();
+    container
+        .resolver(move |c| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            callback(c)
+        })
+        .await;
+
+    spy
+}
+
+/// Panics if an instance of the type cannot be resolved from the implicit container
+pub async fn assert_resolvable<T: Clone + 'static>() {
+    if let Err(e) = helpers::try_get_type::<T>().await {
+        panic!("expected {} to be resolvable: {e}", type_name::<T>());
+    }
+}
+
+/// Panics if an instance of the type can be resolved from the implicit container
+pub async fn assert_not_resolvable<T: Clone + 'static>() {
+    if helpers::try_get_type::<T>().await.is_ok() {
+        panic!("expected {} not to be resolvable", type_name::<T>());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_test_container() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Fixture(u8);
+
+        let outer = TestContainer::new();
+        helpers::set_type(Fixture(1)).await;
+        assert!(helpers::service_container().is_isolated());
+        assert_eq!(helpers::service_container().id(), outer.id());
+
+        {
+            let inner = TestContainer::new();
+            assert_eq!(helpers::service_container().id(), inner.id());
+            assert_not_resolvable::<Fixture>().await;
+        }
+
+        assert_resolvable::<Fixture>().await;
+        assert_eq!(helpers::make_proxy().get_type().await, Some(Fixture(1)));
+
+        drop(outer);
+        assert!(!helpers::service_container().is_isolated());
+        assert_eq!(helpers::get_type::<Fixture>().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_override_type() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Endpoint(&'static str);
+        #[derive(Debug, Clone, PartialEq)]
+        struct Fresh;
+
+        let test = TestContainer::new();
+        test.resolver(|_| async { Endpoint("resolved") }).await;
+
+        {
+            let _guard = override_type(Endpoint("fake")).await;
+            let _fresh = override_type(Fresh).await;
+            assert_eq!(helpers::get_type().await, Some(Endpoint("fake")));
+            assert_eq!(helpers::get_type().await, Some(Fresh));
+        }
+
+        assert_eq!(helpers::get_type().await, Some(Endpoint("resolved")));
+        assert_not_resolvable::<Fresh>().await;
+
+        test.set_type(1_u8).await;
+        let mut watch = test.watch::<u8>().await;
+        let guard = override_type(2_u8).await;
+        assert_eq!(watch.get_and_update(), Some(2));
+        drop(guard);
+        assert!(watch.has_changed());
+        assert_eq!(watch.get(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_spy_resolver() {
+        #[derive(Debug, Clone)]
+        struct Clock;
+
+        let _test = TestContainer::new();
+        let spy = spy_resolver(|_| async { Clock }).await;
+        spy.assert_called(0);
+
+        assert_resolvable::<Clock>().await;
+        helpers::resolve_all::<(Clock,)>().await;
+
+        spy.assert_called(2);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "to be resolvable")]
+    async fn test_assert_resolvable_panics() {
+        let _test = TestContainer::new();
+        assert_resolvable::<u128>().await;
+    }
+}
@@ -0,0 +1,307 @@
+//! Tonic integration
+//!
+//! `ScopeInterceptor` creates a container for every RPC, registers the request's
+//! `MetadataMap` in it and stores it in the request's extensions. It is a child of the
+//! configured parent, otherwise of the implicit container or a proxy of the global
+//! container. Handlers get the container back with `request_container` or resolve
+//! values from it with `inject`.
+//!
+//! Interceptors cannot wrap the handler, so the RPC container is not installed as the
+//! implicit container and `helpers::*` calls made by a handler do not see it. Handlers
+//! that need them can run their body with `with_container(request_container(&request), ...)`.
+//!
+//! `resolve_service` builds the gRPC service implementation itself from a container:
+//!
+//! ```ignore
+//! let greeter = busybody::tonic::resolve_service::<MyGreeter>(&container).await?;
+//! Server::builder()
+//!     .add_service(GreeterServer::with_interceptor(greeter, ScopeInterceptor::with_parent(container)))
+//! ```
+
+use ::tonic::{Request, Status, metadata::MetadataMap, service::Interceptor};
+
+use crate::{ResolveError, Resolver, ServiceContainer, current, helpers::service_container};
+
+/// An interceptor that creates a child container for every RPC
+#[derive(Debug, Clone, Default)]
+pub struct ScopeInterceptor {
+    parent: Option<ServiceContainer>,
+}
+
+impl ScopeInterceptor {
+    /// RPC containers will be children of the implicit container,
+    /// or proxies of the global container when none is installed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// RPC containers will be children of the provided container
+    pub fn with_parent(parent: ServiceContainer) -> Self {
+        Self {
+            parent: Some(parent),
+        }
+    }
+}
+
+impl Interceptor for ScopeInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let scope = current::request_scope(self.parent.as_ref());
+
+        if !scope.try_remember::<MetadataMap>(request.metadata().clone()) {
+            return Err(Status::internal("could not register the request metadata"));
+        }
+        request.extensions_mut().insert(scope);
+
+        Ok(request)
+    }
+}
+
+/// Returns the container of the RPC
+///
+/// Falls back to the implicit container when the request was not handled
+/// by `ScopeInterceptor`
+pub fn request_container<M>(request: &Request<M>) -> ServiceContainer {
+    request
+        .extensions()
+        .get::<ServiceContainer>()
+        .cloned()
+        .unwrap_or_else(service_container)
+}
+
+/// Resolves an instance of `T` from the container of the RPC
+pub async fn inject<T, M>(request: &Request<M>) -> Result<T, Status>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    Ok(request_container(request).try_get_type::<T>().await?)
+}
+
+/// Resolves the gRPC service implementation from the container
+///
+/// A registered instance or resolver is used when one exists, otherwise the
+/// service is built by its `Resolver` implementation
+pub async fn resolve_service<S>(container: &ServiceContainer) -> Result<S, ResolveError>
+where
+    S: Resolver + Clone + Send + Sync + 'static,
+{
+    match container.try_get_type::<S>().await {
+        Err(ResolveError::NotFound { .. }) => S::try_resolve(container).await,
+        result => result,
+    }
+}
+
+impl From<ResolveError> for Status {
+    fn from(error: ResolveError) -> Self {
+        Status::internal(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::Arc,
+        task::{Context, Poll},
+    };
+
+    use ::tonic::{
+        Code, Response,
+        body::Body,
+        codegen::{BoxFuture, Service, http},
+        server::{Grpc, NamedService, UnaryService},
+        service::interceptor::InterceptedService,
+        transport::{Channel, Server, server::TcpIncoming},
+    };
+    use tonic_prost::ProstCodec;
+
+    use super::*;
+
+    const PATH: &str = "/busybody.test.Greeter/SayHello";
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct HelloRequest {
+        #[prost(string, tag = "1")]
+        name: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct HelloReply {
+        #[prost(string, tag = "1")]
+        message: String,
+    }
+
+    #[derive(Debug, Clone)]
+    struct Greeting(&'static str);
+
+    #[derive(Debug, Clone)]
+    struct Unknown;
+
+    #[derive(Clone)]
+    struct Greeter {
+        greeting: Greeting,
+    }
+
+    #[crate::async_trait]
+    impl Resolver for Greeter {
+        async fn resolve(container: &ServiceContainer) -> Self {
+            Self {
+                greeting: container.get_type().await.unwrap(),
+            }
+        }
+    }
+
+    impl Greeter {
+        async fn say_hello(
+            &self,
+            request: Request<HelloRequest>,
+        ) -> Result<Response<HelloReply>, Status> {
+            let metadata = inject::<MetadataMap, _>(&request).await?;
+            let user = metadata
+                .get("x-user")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            if request.get_ref().name == "unknown" {
+                inject::<Unknown, _>(&request).await?;
+            }
+
+            Ok(Response::new(HelloReply {
+                message: format!("{} {} from {user}", self.greeting.0, request.get_ref().name),
+            }))
+        }
+    }
+
+    /// A hand written version of the server tonic generates for a single unary method
+    #[derive(Clone)]
+    struct GreeterServer(Arc<Greeter>);
+
+    impl NamedService for GreeterServer {
+        const NAME: &'static str = "busybody.test.Greeter";
+    }
+
+    impl UnaryService<HelloRequest> for GreeterServer {
+        type Response = HelloReply;
+        type Future = BoxFuture<Response<HelloReply>, Status>;
+
+        fn call(&mut self, request: Request<HelloRequest>) -> Self::Future {
+            let greeter = self.0.clone();
+            Box::pin(async move { greeter.say_hello(request).await })
+        }
+    }
+
+    impl Service<http::Request<Body>> for GreeterServer {
+        type Response = http::Response<Body>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+            let service = self.clone();
+            Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::<HelloReply, HelloRequest>::default());
+                Ok(grpc.unary(service, request).await)
+            })
+        }
+    }
+
+    async fn serve(container: ServiceContainer) -> SocketAddr {
+        let greeter = resolve_service::<Greeter>(&container).await.unwrap();
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let address = incoming.local_addr().unwrap();
+
+        tokio::spawn(
+            Server::builder()
+                .add_service(InterceptedService::new(
+                    GreeterServer(Arc::new(greeter)),
+                    ScopeInterceptor::with_parent(container),
+                ))
+                .serve_with_incoming(incoming),
+        );
+
+        address
+    }
+
+    async fn say_hello(address: SocketAddr, name: &str) -> Result<String, Status> {
+        let channel = Channel::from_shared(format!("http://{address}"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = ::tonic::client::Grpc::new(channel);
+        client.ready().await.unwrap();
+
+        let mut request = Request::new(HelloRequest {
+            name: name.to_string(),
+        });
+        request
+            .metadata_mut()
+            .insert("x-user", "busybody".parse().unwrap());
+
+        let response = client
+            .unary(
+                request,
+                http::uri::PathAndQuery::from_static(PATH),
+                ProstCodec::<HelloRequest, HelloReply>::default(),
+            )
+            .await?;
+
+        Ok(response.into_inner().message)
+    }
+
+    #[tokio::test]
+    async fn test_rpc_scope_has_metadata() {
+        let container = ServiceContainer::proxy();
+        container.set_type(Greeting("hello")).await;
+
+        let address = serve(container.clone()).await;
+
+        assert_eq!(
+            say_hello(address, "world").await.unwrap(),
+            "hello world from busybody"
+        );
+        assert!(container.get_type::<MetadataMap>().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_error_becomes_status() {
+        let container = ServiceContainer::proxy();
+        container.set_type(Greeting("hello")).await;
+
+        let address = serve(container).await;
+        let status = say_hello(address, "unknown").await.unwrap_err();
+
+        assert_eq!(status.code(), Code::Internal);
+        assert!(status.message().contains("Unknown"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_service_prefers_registered_instance() {
+        let container = ServiceContainer::proxy();
+        container
+            .set_type(Greeter {
+                greeting: Greeting("registered"),
+            })
+            .await;
+
+        let greeter = resolve_service::<Greeter>(&container).await.unwrap();
+        assert_eq!(greeter.greeting.0, "registered");
+    }
+
+    #[tokio::test]
+    async fn test_rpc_scope_defaults_to_implicit_container() {
+        let tenant = ServiceContainer::proxy();
+        tenant.set_type(Greeting("tenant")).await;
+
+        let request = crate::with_container(tenant, async {
+            ScopeInterceptor::new().call(Request::new(())).unwrap()
+        })
+        .await;
+
+        let greeting = inject::<Greeting, _>(&request).await.unwrap();
+        assert_eq!(greeting.0, "tenant");
+    }
+}
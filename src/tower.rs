@@ -0,0 +1,303 @@
+//! Tower integration
+//!
+//! `ContainerLayer` wraps any tower service and creates a scoped container for
+//! every call. The scope is a child of the configured parent, otherwise of the
+//! implicit container or a proxy of the global container. It is installed as the
+//! implicit container while the inner service handles the request.
+//!
+//! Every instance and resolver registered in the scope is dropped as soon as the
+//! response future completes or is dropped, even if a clone of the scope escaped.
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::future::BoxFuture;
+use tower_layer::Layer;
+use tower_service::Service as TowerService;
+
+use crate::{ServiceContainer, current};
+
+/// Prepares the scope of a call before the inner service is called
+pub trait Seed<Request>: Clone + Send + Sync + 'static {
+    /// Registers values taken from the request in the scope
+    fn seed(&self, request: &mut Request, scope: ServiceContainer) -> BoxFuture<'static, ()>;
+}
+
+/// Leaves the scope empty
+impl<Request> Seed<Request> for () {
+    fn seed(&self, _: &mut Request, _: ServiceContainer) -> BoxFuture<'static, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// Registers a clone of the whole request in the scope
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InsertRequest;
+
+impl<Request: Clone + Send + Sync + 'static> Seed<Request> for InsertRequest {
+    fn seed(&self, request: &mut Request, scope: ServiceContainer) -> BoxFuture<'static, ()> {
+        let request = request.clone();
+        Box::pin(async move {
+            scope.set_type(request).await;
+        })
+    }
+}
+
+/// Seeds the scope by calling a function. See `ContainerLayer::seed`
+pub struct SeedFn<F>(Arc<F>);
+
+impl<F> Clone for SeedFn<F> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<Request, F, Fut> Seed<Request> for SeedFn<F>
+where
+    F: Fn(&mut Request, ServiceContainer) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn seed(&self, request: &mut Request, scope: ServiceContainer) -> BoxFuture<'static, ()> {
+        Box::pin((self.0)(request, scope))
+    }
+}
+
+/// A tower layer that creates a scoped container for every call
+#[derive(Debug, Clone, Default)]
+pub struct ContainerLayer<K = ()> {
+    parent: Option<ServiceContainer>,
+    seed: K,
+}
+
+impl ContainerLayer {
+    /// Scopes will be children of the implicit container,
+    /// or proxies of the global container when none is installed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scopes will be children of the provided container
+    pub fn with_parent(parent: ServiceContainer) -> Self {
+        Self {
+            parent: Some(parent),
+            seed: (),
+        }
+    }
+}
+
+impl<K> ContainerLayer<K> {
+    /// Registers a clone of every request in its scope
+    pub fn insert_request(self) -> ContainerLayer<InsertRequest> {
+        ContainerLayer {
+            parent: self.parent,
+            seed: InsertRequest,
+        }
+    }
+
+    /// Calls the function with every request and its scope before the inner service is called
+    ///
+    /// The function can register selected parts of the request in the scope or
+    /// modify the request, for example to store the scope in its extensions
+    pub fn seed<Request, F, Fut>(self, seed: F) -> ContainerLayer<SeedFn<F>>
+    where
+        F: Fn(&mut Request, ServiceContainer) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        ContainerLayer {
+            parent: self.parent,
+            seed: SeedFn(Arc::new(seed)),
+        }
+    }
+}
+
+impl<S, K: Clone> Layer<S> for ContainerLayer<K> {
+    type Service = ContainerService<S, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ContainerService {
+            inner,
+            parent: self.parent.clone(),
+            seed: self.seed.clone(),
+        }
+    }
+}
+
+/// The service created by `ContainerLayer`
+#[derive(Debug, Clone)]
+pub struct ContainerService<S, K = ()> {
+    inner: S,
+    parent: Option<ServiceContainer>,
+    seed: K,
+}
+
+impl<S, K, Request> TowerService<Request> for ContainerService<S, K>
+where
+    S: TowerService<Request> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    K: Seed<Request>,
+    Request: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        // The clone may not be ready, keep the service that was polled
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let parent = self.parent.clone();
+        let seed = self.seed.clone();
+
+        Box::pin(async move {
+            let scope = current::request_scope(parent.as_ref());
+            let _guard = scope.scope_guard();
+            seed.seed(&mut request, scope.clone()).await;
+
+            current::with_container(scope, async move { inner.call(request).await }).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        convert::Infallible,
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, Ordering},
+        },
+        time::Duration,
+    };
+
+    use ::tower::{ServiceBuilder, ServiceExt, service_fn};
+
+    use super::*;
+    use crate::helpers;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Request(&'static str);
+
+    /// Flags when the last clone is dropped
+    #[derive(Clone)]
+    struct Resource(#[allow(dead_code)] Arc<DropFlag>);
+
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_is_inserted_in_scope() {
+        let root = ServiceContainer::proxy();
+        root.set_type(7_u32).await;
+
+        let service = ServiceBuilder::new()
+            .layer(ContainerLayer::with_parent(root.clone()).insert_request())
+            .service(service_fn(|_: Request| async {
+                let request = helpers::get_type::<Request>().await.unwrap();
+                let number = helpers::get_type::<u32>().await.unwrap();
+                Ok::<_, Infallible>(format!("{} {}", request.0, number))
+            }));
+
+        let response = service.oneshot(Request("hello")).await.unwrap();
+        assert_eq!(response, "hello 7");
+        assert!(root.get_type::<Request>().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_seed_selected_parts() {
+        let service = ServiceBuilder::new()
+            .layer(
+                ContainerLayer::new().seed(|request: &mut Request, scope: ServiceContainer| {
+                    let length = request.0.len();
+                    request.0 = "seeded";
+                    async move {
+                        scope.set_type(length).await;
+                    }
+                }),
+            )
+            .service(service_fn(|request: Request| async move {
+                let length = helpers::get_type::<usize>().await.unwrap();
+                Ok::<_, Infallible>(format!("{} {}", request.0, length))
+            }));
+
+        let response = service.oneshot(Request("four")).await.unwrap();
+        assert_eq!(response, "seeded 4");
+    }
+
+    #[tokio::test]
+    async fn test_scope_is_disposed_on_completion() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let escaped = Arc::new(Mutex::new(None));
+
+        let flag = dropped.clone();
+        let slot = escaped.clone();
+        let service = ContainerLayer::new().layer(service_fn(move |_: Request| {
+            let flag = flag.clone();
+            let slot = slot.clone();
+            async move {
+                let scope = helpers::service_container();
+                scope.set_type(Resource(Arc::new(DropFlag(flag)))).await;
+                *slot.lock().unwrap() = Some(scope);
+                Ok::<_, Infallible>(())
+            }
+        }));
+
+        service.oneshot(Request("")).await.unwrap();
+
+        assert!(dropped.load(Ordering::SeqCst));
+        let scope = escaped.lock().unwrap().take().unwrap();
+        assert!(scope.get_type::<Resource>().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scope_is_disposed_when_dropped() {
+        let dropped = Arc::new(AtomicBool::new(false));
+
+        let flag = dropped.clone();
+        let service = ContainerLayer::new().layer(service_fn(move |_: Request| {
+            let flag = flag.clone();
+            async move {
+                helpers::set_type(Resource(Arc::new(DropFlag(flag)))).await;
+                futures::future::pending::<()>().await;
+                Ok::<_, Infallible>(())
+            }
+        }));
+
+        let result =
+            tokio::time::timeout(Duration::from_millis(20), service.oneshot(Request(""))).await;
+
+        assert!(result.is_err());
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_scope_defaults_to_implicit_container() {
+        let tenant = ServiceContainer::proxy();
+        tenant.set_type(7_u32).await;
+
+        let service = ContainerLayer::new().layer(service_fn(|_: Request| {
+            // Called before the response future is polled
+            let scope = helpers::service_container();
+            async move {
+                let number = scope.get_type::<u32>().await;
+                Ok::<_, Infallible>((scope.parent().map(|p| p.id()), number))
+            }
+        }));
+
+        let (parent, number) = crate::with_container(tenant.clone(), service.oneshot(Request("")))
+            .await
+            .unwrap();
+        assert_eq!(parent, Some(tenant.id()));
+        assert_eq!(number, Some(7));
+    }
+}
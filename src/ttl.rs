@@ -0,0 +1,138 @@
+//! The cache behind the resolvers registered with `resolver_ttl`
+//!
+//! Each registration owns its cache. Lookups made while the value is being
+//! produced wait for it instead of calling the factory again.
+
+use std::{
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+
+use crate::{ServiceContainer, runtime};
+
+/// A cache that can be emptied without knowing the type of its value
+pub(crate) trait Invalidate: Send + Sync {
+    fn invalidate(&self);
+}
+
+pub(crate) type TtlFactory<T> = dyn Fn(ServiceContainer) -> BoxFuture<'static, T> + Send + Sync;
+
+struct Cached<T> {
+    value: T,
+    expires_at: Instant,
+}
+
+pub(crate) struct TtlCache<T> {
+    ttl: Duration,
+    serve_stale: bool,
+    cached: Mutex<Option<Cached<T>>>,
+    /// Held while the factory runs
+    flight: runtime::Mutex<()>,
+    refreshing: AtomicBool,
+    /// Incremented on invalidation so values produced before it are not stored
+    generation: AtomicU64,
+}
+
+impl<T: Clone + Send + Sync + 'static> TtlCache<T> {
+    pub(crate) fn new(ttl: Duration, serve_stale: bool) -> Self {
+        Self {
+            ttl,
+            serve_stale,
+            cached: Mutex::new(None),
+            flight: runtime::Mutex::new(()),
+            refreshing: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached value and whether it is still fresh
+    fn cached(&self) -> Option<(T, bool)> {
+        let cached = self.cached.lock().unwrap_or_else(PoisonError::into_inner);
+        cached
+            .as_ref()
+            .map(|cached| (cached.value.clone(), cached.expires_at > Instant::now()))
+    }
+
+    fn fresh(&self) -> Option<T> {
+        self.cached()
+            .and_then(|(value, fresh)| fresh.then_some(value))
+    }
+
+    fn store(&self, value: T, generation: u64) {
+        let mut cached = self.cached.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.generation.load(Ordering::SeqCst) == generation {
+            *cached = Some(Cached {
+                value,
+                expires_at: Instant::now() + self.ttl,
+            });
+        }
+    }
+
+    /// Returns the cached value, calling the factory once it expired
+    pub(crate) async fn get(
+        self: Arc<Self>,
+        container: ServiceContainer,
+        factory: Arc<TtlFactory<T>>,
+    ) -> T {
+        match self.cached() {
+            Some((value, true)) => return value,
+            Some((value, false)) if self.serve_stale => {
+                self.refresh(container, factory);
+                return value;
+            }
+            _ => (),
+        }
+
+        let _flight = self.flight.lock().await;
+        // The value may have been produced while waiting
+        if let Some(value) = self.fresh() {
+            return value;
+        }
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let value = factory(container).await;
+        self.store(value.clone(), generation);
+
+        value
+    }
+
+    /// Produces a new value in the background unless a refresh is running
+    fn refresh(self: Arc<Self>, container: ServiceContainer, factory: Arc<TtlFactory<T>>) {
+        struct Refreshing<T>(Arc<TtlCache<T>>);
+
+        impl<T> Drop for Refreshing<T> {
+            fn drop(&mut self) {
+                self.0.refreshing.store(false, Ordering::SeqCst);
+            }
+        }
+
+        if self.refreshing.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // Resets the flag even when the task never runs
+        let refreshing = Refreshing(self);
+        runtime::spawn_detached(async move {
+            let cache = &refreshing.0;
+            let _flight = cache.flight.lock().await;
+            if cache.fresh().is_none() {
+                let generation = cache.generation.load(Ordering::SeqCst);
+                let value = factory(container).await;
+                cache.store(value, generation);
+            }
+        });
+    }
+}
+
+impl<T: Send + Sync> Invalidate for TtlCache<T> {
+    fn invalidate(&self) {
+        let mut cached = self.cached.lock().unwrap_or_else(PoisonError::into_inner);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        *cached = None;
+    }
+}
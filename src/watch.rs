@@ -0,0 +1,226 @@
+//! Receivers notified when a registered type is replaced
+//!
+//! Each store keeps one channel per watched type. Setting, updating or forgetting
+//! the type in that store publishes the new value to every receiver.
+
+use std::{
+    any::Any,
+    fmt::Display,
+    sync::{
+        Arc, PoisonError, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use event_listener::Event;
+
+use crate::{Resolver, ServiceContainer};
+
+/// The sending side of a channel, without the type of its values
+pub(crate) trait Publisher: Send + Sync {
+    /// Replaces the value of the channel, the value must be of the watched type
+    fn publish(&self, value: Option<&(dyn Any + Send + Sync)>);
+
+    /// Wakes up every receiver, no more values will be published
+    fn close(&self);
+
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+}
+
+struct Slot<T> {
+    version: u64,
+    value: Option<T>,
+}
+
+pub(crate) struct Channel<T> {
+    slot: RwLock<Slot<T>>,
+    event: Event,
+    closed: AtomicBool,
+}
+
+impl<T> Channel<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            slot: RwLock::new(Slot {
+                version: 0,
+                value: None,
+            }),
+            event: Event::new(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn version(&self) -> u64 {
+        self.slot
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .version
+    }
+
+    fn send(&self, value: Option<T>) {
+        let mut slot = self.slot.write().unwrap_or_else(PoisonError::into_inner);
+        slot.version += 1;
+        slot.value = value;
+        drop(slot);
+
+        self.event.notify(usize::MAX);
+    }
+
+    /// Sets the first value unless one was already published
+    pub(crate) fn init(&self, value: Option<T>) {
+        let mut slot = self.slot.write().unwrap_or_else(PoisonError::into_inner);
+        if slot.version == 0 {
+            slot.value = value;
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Publisher for Channel<T> {
+    fn publish(&self, value: Option<&(dyn Any + Send + Sync)>) {
+        self.send(value.and_then(|value| value.downcast_ref::<T>()).cloned());
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.event.notify(usize::MAX);
+    }
+
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+/// The store the receivers watch was disposed, no more values will be received
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchClosed;
+
+impl Display for WatchClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the watched container was disposed")
+    }
+}
+
+impl std::error::Error for WatchClosed {}
+
+/// Receives the latest value of a registered type. See `ServiceContainer::watch`
+///
+/// Like a `tokio::sync::watch::Receiver`, intermediate values are skipped when
+/// the receiver does not keep up, only the latest one is kept
+pub struct Watch<T> {
+    channel: Arc<Channel<T>>,
+    seen: u64,
+}
+
+impl<T: Clone> Watch<T> {
+    pub(crate) fn new(channel: Arc<Channel<T>>) -> Self {
+        let seen = channel.version();
+        Self { channel, seen }
+    }
+
+    /// Returns the latest value. None is returned once the type was forgotten
+    pub fn get(&self) -> Option<T> {
+        self.channel
+            .slot
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .value
+            .clone()
+    }
+
+    /// Returns the latest value and marks it as seen
+    pub fn get_and_update(&mut self) -> Option<T> {
+        let slot = self
+            .channel
+            .slot
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.seen = slot.version;
+        slot.value.clone()
+    }
+
+    /// Returns true if a value was published since the last one seen
+    pub fn has_changed(&self) -> bool {
+        self.channel.version() != self.seen
+    }
+
+    /// Waits for a value that was not seen yet and marks it as seen
+    ///
+    /// An error is returned when the watched container is disposed
+    pub async fn changed(&mut self) -> Result<(), WatchClosed> {
+        loop {
+            let version = self.channel.version();
+            if version != self.seen {
+                self.seen = version;
+                return Ok(());
+            }
+            if self.channel.closed.load(Ordering::SeqCst) {
+                return Err(WatchClosed);
+            }
+
+            let listener = self.channel.event.listen();
+            // A value may have been published before the listener was registered
+            if self.channel.version() == self.seen && !self.channel.closed.load(Ordering::SeqCst) {
+                listener.await;
+            }
+        }
+    }
+}
+
+impl<T> Clone for Watch<T> {
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+/// An injectable handle that always reads the latest instance of the type
+///
+/// Register it with `resolvable::<Live<T>>()` and inject `Live<T>` instead of `T`
+/// in long lived services. Values produced by resolvers are read once, when the
+/// handle is resolved
+///
+/// ```rust
+/// # use busybody::*;
+/// # #[tokio::main]
+/// # async fn main() {
+/// #[derive(Debug, Clone, PartialEq)]
+/// struct Config(u8);
+///
+/// let container = ServiceContainer::proxy();
+/// container.set_type(Config(1)).await;
+/// container.resolvable::<Live<Config>>().await;
+///
+/// let config = container.get_type::<Live<Config>>().await.unwrap();
+/// container.set_type(Config(2)).await;
+///
+/// assert_eq!(config.get(), Some(Config(2)));
+/// # }
+/// ```
+pub struct Live<T>(Watch<T>);
+
+impl<T: Clone> Live<T> {
+    /// Returns the latest instance. None is returned once the type was forgotten
+    pub fn get(&self) -> Option<T> {
+        self.0.get()
+    }
+
+    /// Returns a receiver notified when the instance is replaced
+    pub fn watch(&self) -> Watch<T> {
+        self.0.clone()
+    }
+}
+
+impl<T> Clone for Live<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Clone + Send + Sync + 'static> Resolver for Live<T> {
+    async fn resolve(container: &ServiceContainer) -> Self {
+        Self(container.watch::<T>().await)
+    }
+}